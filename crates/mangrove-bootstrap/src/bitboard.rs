@@ -264,51 +264,18 @@ impl BitBoard {
         0b11111111
     );
 
-    // Used to check both if a piece attacks a spot between the king and rook and if the space
-    // between them is empty.
-    pub const WHITE_KING_SIDE_CASTLE_MASK: Self = bb!(
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000110
-    );
-
-    // Used to check if there are any pieces between the rook and king
-    pub const WHITE_QUEEN_SIDE_CASTLE_OCCUPATION_MASK: Self = bb!(
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b01110000
-    );
-
-    // Used to check if there are any attacks between the king and the king's final spot
-    pub const WHITE_QUEEN_SIDE_CASTLE_ATTACK_MASK: Self = bb!(
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00110000
+    /// A bitboard containing `1`s for each light square of the board, such as H1 or A8.
+    pub const LIGHT_SQUARES: Self = bb!(
+        0b10101010
+        0b01010101
+        0b10101010
+        0b01010101
+        0b10101010
+        0b01010101
+        0b10101010
+        0b01010101
     );
 
-    pub const BLACK_KING_SIDE_CASTLE_MASK: Self = Self::WHITE_KING_SIDE_CASTLE_MASK.vertical_flip();
-
-    pub const BLACK_QUEEN_SIDE_CASTLE_ATTACK_MASK: Self =
-        Self::WHITE_QUEEN_SIDE_CASTLE_ATTACK_MASK.vertical_flip();
-
-    pub const BLACK_QUEEN_SIDE_CASTLE_OCCUPATION_MASK: Self =
-        Self::WHITE_QUEEN_SIDE_CASTLE_OCCUPATION_MASK.vertical_flip();
-
     pub const PAWN_START_RANKS: Self = bb!(
         0b00000000
         0b11111111
@@ -342,38 +309,6 @@ impl BitBoard {
         0b00000000
     );
 
-    pub const KING_CASTLE_MOVES: Self = bb!(
-        0b00101010
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00000000
-        0b00101010
-    );
-
-    pub fn king_side_castle_mask(color: Color) -> Self {
-        match color {
-            Color::White => Self::WHITE_KING_SIDE_CASTLE_MASK,
-            Color::Black => Self::BLACK_KING_SIDE_CASTLE_MASK,
-        }
-    }
-
-    pub fn queen_side_castle_occupation_mask(color: Color) -> Self {
-        match color {
-            Color::White => Self::WHITE_QUEEN_SIDE_CASTLE_OCCUPATION_MASK,
-            Color::Black => Self::BLACK_QUEEN_SIDE_CASTLE_OCCUPATION_MASK,
-        }
-    }
-
-    pub fn queen_side_castle_attack_mask(color: Color) -> Self {
-        match color {
-            Color::White => Self::WHITE_QUEEN_SIDE_CASTLE_ATTACK_MASK,
-            Color::Black => Self::BLACK_QUEEN_SIDE_CASTLE_ATTACK_MASK,
-        }
-    }
-
     /// Checks if the bitboard contains a single `1` bit.
     pub fn is_a_single_one(&self) -> bool {
         self.0.is_power_of_two()
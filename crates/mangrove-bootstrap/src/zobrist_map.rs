@@ -8,8 +8,14 @@ pub struct ZobristSide {
 }
 
 #[derive(Debug, StandardDist, ToTokenStream)]
-// This contains all configurations of castling rights for each player, as 2^4 = 16
-pub struct ZobristCastlingRights(pub [u64; 16]);
+// This contains each castling right of each player, by the file of the rook it castles with, so
+// Chess960 rights with different rooks hash differently
+pub struct ZobristCastlingRights {
+    pub white_king_side: [u64; 8],
+    pub white_queen_side: [u64; 8],
+    pub black_king_side: [u64; 8],
+    pub black_queen_side: [u64; 8],
+}
 
 #[derive(Debug, StandardDist, ToTokenStream)]
 pub struct ZobristPieces {
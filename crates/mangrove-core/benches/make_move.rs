@@ -0,0 +1,71 @@
+use std::str::FromStr;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use mangrove_core::{board::Board, mg};
+
+const POSITIONS: [(&str, &str); 2] = [
+    (
+        "starting position",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    ),
+    (
+        "kiwipete",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    ),
+];
+
+const DEPTH: u32 = 3;
+
+// Perft as it was done before unmaking moves was possible: every child is a full copy of its
+// parent.
+fn copy_make_perft(board: &Board, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    board
+        .gen_child_boards()
+        .map(|(_, child_board)| copy_make_perft(&child_board, depth - 1))
+        .sum()
+}
+
+fn make_unmake_perft(board: &mut Board, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    mg::gen_moves(board)
+        .into_iter()
+        .map(|chess_move| {
+            let undo_record = board.make_move(chess_move).unwrap();
+            let nodes = make_unmake_perft(board, depth - 1);
+            // SAFETY: The record was returned by the last move made, whose subtree was taken back
+            unsafe { board.unmake_move(undo_record) }.unwrap();
+
+            nodes
+        })
+        .sum()
+}
+
+fn make_move_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("perft");
+
+    for (name, fen) in POSITIONS {
+        let board = Board::from_str(fen).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("copy-make", name), &board, |b, board| {
+            b.iter(|| copy_make_perft(board, DEPTH))
+        });
+
+        group.bench_with_input(BenchmarkId::new("make-unmake", name), &board, |b, board| {
+            let mut board = *board;
+
+            b.iter(|| make_unmake_perft(&mut board, DEPTH))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, make_move_benchmark);
+criterion_main!(benches);
@@ -10,7 +10,11 @@ use crate::{
     index,
     index::zobrist,
     mg,
-    repr::{ChessMove, ParsePieceBoardError, Piece, PieceBoard, PieceKind, PieceKindBoard, Player},
+    perft::PerftTable,
+    repr::{
+        CastlingRights, ChessMove, ParsePieceBoardError, Piece, PieceBoard, PieceKind,
+        PieceKindBoard, Player,
+    },
 };
 use mangrove_bootstrap::{BitBoard, Color, ParseSquareError, Square};
 
@@ -32,6 +36,54 @@ pub struct Board {
 #[error("move is invalid for used board")]
 pub struct MakeMoveError;
 
+#[derive(Debug, thiserror::Error)]
+#[error("undo record was not created by the last move made on the board")]
+pub struct UnmakeMoveError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Contains everything needed to take back a move made on a board, as returned by
+/// [`Board::make_move`]. Pass it to [`Board::unmake_move`] to restore the board to the exact state
+/// it was in before the move.
+pub struct UndoRecord {
+    chess_move: ChessMove,
+    moved_piece_kind: PieceKind,
+    // The captured piece, and the square it was captured on, which differs from the target square
+    // for en passant captures.
+    captured_piece: Option<(Square, PieceKind)>,
+    // The origin and target squares of the rook, if the move was a castle.
+    castling_rook_move: Option<(Square, Square)>,
+    us_castling_rights: CastlingRights,
+    them_castling_rights: CastlingRights,
+    checkers: BitBoard,
+    pinned: BitBoard,
+    en_passant_capture_square: Option<Square>,
+    min_ply_clock: u8,
+    hash: u64,
+    // The hash of the board after the move, used to verify the record belongs to the board.
+    next_hash: u64,
+}
+
+/// Returns the squares the king and rook end up on when the king on `king_square` castles with the
+/// rook on `rook_square`. As in standard chess, the king always ends up on the C or G file, and the
+/// rook right next to it, on the D or F file.
+pub fn castling_targets(king_square: Square, rook_square: Square) -> (Square, Square) {
+    let (king_file, rook_file) = if rook_square.file() > king_square.file() {
+        (Square::G_FILE, Square::F_FILE)
+    } else {
+        (Square::C_FILE, Square::D_FILE)
+    };
+
+    let rank_start = king_square.rank() * 8;
+
+    // SAFETY: Both squares are on the rank of the king
+    unsafe {
+        (
+            Square::try_from(rank_start + king_file).unwrap_unchecked(),
+            Square::try_from(rank_start + rook_file).unwrap_unchecked(),
+        )
+    }
+}
+
 impl Board {
     pub fn starting_position() -> Self {
         Self::from_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap()
@@ -80,6 +132,36 @@ impl Board {
         !self.checkers.is_empty()
     }
 
+    /// Returns the number of plies played since the start of the game, as told by the full move
+    /// counter.
+    pub fn ply(&self) -> u32 {
+        (self.full_moves.max(1) as u32 - 1) * 2 + (self.playing_color == Color::Black) as u32
+    }
+
+    /// Checks if neither player has enough material left to ever checkmate the other, meaning the
+    /// position is dead. This covers a lone king against a king with at most one minor piece, and
+    /// boards whose only remaining minor pieces are bishops that all stand on squares of one color.
+    pub fn has_insufficient_material(&self) -> bool {
+        let pawns_and_major_pieces = self.us.pawns
+            | self.us.rooks
+            | self.us.queens
+            | self.them.pawns
+            | self.them.rooks
+            | self.them.queens;
+
+        if !pawns_and_major_pieces.is_empty() {
+            return false;
+        }
+
+        let knights = self.us.knights | self.them.knights;
+        let bishops = self.us.bishops | self.them.bishops;
+
+        (knights | bishops).count_ones() <= 1
+            || (knights.is_empty()
+                && (bishops.is_subset_of(BitBoard::LIGHT_SQUARES)
+                    || bishops.is_subset_of(!BitBoard::LIGHT_SQUARES)))
+    }
+
     pub fn occupation(&self) -> BitBoard {
         self.us.occupation | self.them.occupation
     }
@@ -98,6 +180,7 @@ impl Board {
     // INVARIANT: A piece as specified must NOT exist on the specified square.
     unsafe fn add_piece_unchecked(&mut self, square: Square, piece: Piece) {
         self.piece_kind_board[square] = Some(piece.kind);
+        self.hash ^= zobrist::piece(piece, square);
 
         if piece.color == self.playing_color {
             &mut self.us
@@ -110,6 +193,7 @@ impl Board {
     // INVARIANT: A piece as specified must exist on the specified square.
     unsafe fn remove_piece_unchecked(&mut self, square: Square, piece: Piece) {
         self.piece_kind_board[square] = None;
+        self.hash ^= zobrist::piece(piece, square);
 
         if piece.color == self.playing_color {
             &mut self.us
@@ -144,20 +228,46 @@ impl Board {
     }
 
     // INVARIANT: The passed move must be legal in relation to the current board.
-    unsafe fn make_move_unchecked(&mut self, chess_move: ChessMove) {
-        self.en_passant_capture_square = None;
-        self.checkers = BitBoard::EMPTY;
-        self.pinned = BitBoard::EMPTY;
-
+    unsafe fn make_move_unchecked(&mut self, chess_move: ChessMove) -> UndoRecord {
         // SAFETY: The board is assumed to be valid
         let enemy_king_square = Square::try_from(self.them.king).unwrap();
         let moved_piece_kind = self.piece_kind_board[chess_move.origin].unwrap();
         let target_piece_kind = self.piece_kind_board[chess_move.target];
 
-        let mut is_capture = false;
+        // Castles are encoded as the king capturing its own rook
+        let is_castle =
+            moved_piece_kind == PieceKind::King && self.us.rooks.get_bit(chess_move.target);
+
+        let mut undo_record = UndoRecord {
+            chess_move,
+            moved_piece_kind,
+            captured_piece: None,
+            castling_rook_move: None,
+            us_castling_rights: self.us.castling_rights,
+            them_castling_rights: self.them.castling_rights,
+            checkers: self.checkers,
+            pinned: self.pinned,
+            en_passant_capture_square: self.en_passant_capture_square,
+            min_ply_clock: self.min_ply_clock,
+            hash: self.hash,
+            next_hash: 0,
+        };
 
-        self.us.castling_rights[chess_move.origin] = false;
-        self.them.castling_rights[chess_move.target] = false;
+        // Remove the parts of the hash that may change during the move. They are re-applied once
+        // the move has been made. Pieces are hashed as they are added and removed.
+        self.hash ^= self.gen_state_hash();
+
+        self.en_passant_capture_square = None;
+        self.checkers = BitBoard::EMPTY;
+        self.pinned = BitBoard::EMPTY;
+
+        if moved_piece_kind == PieceKind::King {
+            self.us.castling_rights = CastlingRights::empty();
+        } else {
+            self.us.castling_rights.remove_rook(chess_move.origin);
+        }
+
+        self.them.castling_rights.remove_rook(chess_move.target);
 
         // SAFETY: Move is assumed to be legal.
         unsafe {
@@ -169,79 +279,77 @@ impl Board {
                 },
             );
 
-            // Handle removing the captured piece
-            if let Some(target_piece_kind) = target_piece_kind {
+            if is_castle {
+                let (king_target, rook_target) =
+                    castling_targets(chess_move.origin, chess_move.target);
+
                 self.remove_piece_unchecked(
                     chess_move.target,
                     Piece {
-                        kind: target_piece_kind,
-                        color: !self.playing_color,
+                        kind: PieceKind::Rook,
+                        color: self.playing_color,
                     },
                 );
 
-                is_capture = true;
-            } else if moved_piece_kind == PieceKind::Pawn {
-                if chess_move.origin.rank().abs_diff(chess_move.target.rank()) == 2 {
-                    // This must mean the move was a double-push
-                    self.en_passant_capture_square = Some(
-                        chess_move
-                            .target
-                            .move_one_down_unchecked(self.playing_color),
-                    )
-                } else if chess_move.origin.file() != chess_move.target.file() {
-                    // If we are here, this must mean the move was an en passant.
+                self.add_piece_unchecked(
+                    rook_target,
+                    Piece {
+                        kind: PieceKind::Rook,
+                        color: self.playing_color,
+                    },
+                );
+
+                self.add_piece_unchecked(
+                    king_target,
+                    Piece {
+                        kind: PieceKind::King,
+                        color: self.playing_color,
+                    },
+                );
+
+                undo_record.castling_rook_move = Some((chess_move.target, rook_target));
+            } else {
+                // Handle removing the captured piece
+                if let Some(target_piece_kind) = target_piece_kind {
                     self.remove_piece_unchecked(
-                        chess_move
-                            .target
-                            .move_one_down_unchecked(self.playing_color),
+                        chess_move.target,
                         Piece {
-                            kind: PieceKind::Pawn,
+                            kind: target_piece_kind,
                             color: !self.playing_color,
                         },
                     );
 
-                    is_capture = true;
+                    undo_record.captured_piece = Some((chess_move.target, target_piece_kind));
+                } else if moved_piece_kind == PieceKind::Pawn {
+                    if chess_move.origin.rank().abs_diff(chess_move.target.rank()) == 2 {
+                        // This must mean the move was a double-push
+                        self.en_passant_capture_square = Some(
+                            chess_move
+                                .target
+                                .move_one_down_unchecked(self.playing_color),
+                        )
+                    } else if chess_move.origin.file() != chess_move.target.file() {
+                        // If we are here, this must mean the move was an en passant.
+                        let captured_pawn_square = chess_move
+                            .target
+                            .move_one_down_unchecked(self.playing_color);
+
+                        self.remove_piece_unchecked(
+                            captured_pawn_square,
+                            Piece {
+                                kind: PieceKind::Pawn,
+                                color: !self.playing_color,
+                            },
+                        );
+
+                        undo_record.captured_piece = Some((captured_pawn_square, PieceKind::Pawn));
+                    }
                 }
-            }
-
-            self.add_piece_unchecked(
-                chess_move.target,
-                Piece {
-                    kind: chess_move.promotion.unwrap_or(moved_piece_kind),
-                    color: self.playing_color,
-                },
-            );
-        }
-
-        let move_bitboard = BitBoard::from(chess_move.origin) ^ chess_move.target.into();
-
-        // TODO: Check if replacing this with a more rudimentary check would be faster
-        if moved_piece_kind == PieceKind::King
-            && move_bitboard.is_subset_of(BitBoard::KING_CASTLE_MOVES)
-        {
-            // This must mean the move was a castle.
-            let king_square = chess_move.origin.as_index() as u8;
-
-            let (origin, target) = if chess_move.target.file() == Square::G_FILE {
-                (king_square + 3, king_square + 1)
-            } else {
-                (king_square - 4, king_square - 1)
-            };
-
-            // SAFETY: Move is assumed to be legal.
-            unsafe {
-                self.remove_piece_unchecked(
-                    Square::try_from(origin).unwrap_unchecked(),
-                    Piece {
-                        kind: PieceKind::Rook,
-                        color: self.playing_color,
-                    },
-                );
 
                 self.add_piece_unchecked(
-                    Square::try_from(target).unwrap_unchecked(),
+                    chess_move.target,
                     Piece {
-                        kind: PieceKind::Rook,
+                        kind: chess_move.promotion.unwrap_or(moved_piece_kind),
                         color: self.playing_color,
                     },
                 );
@@ -249,7 +357,7 @@ impl Board {
         }
 
         // Update `checkers` for the non-sliding pieces
-        self.checkers ^= match self.piece_kind_board[chess_move.target].unwrap() {
+        self.checkers ^= match chess_move.promotion.unwrap_or(moved_piece_kind) {
             PieceKind::Knight => index::knight_attacks(enemy_king_square) & self.us.knights,
             PieceKind::Pawn => {
                 index::pawn_attacks(enemy_king_square, !self.playing_color) & self.us.pawns
@@ -279,25 +387,218 @@ impl Board {
 
         self.full_moves += (self.playing_color == Color::Black) as u16;
 
-        self.min_ply_clock = if moved_piece_kind == PieceKind::Pawn || is_capture {
-            0
-        } else {
-            self.min_ply_clock.saturating_add(1)
-        };
+        self.min_ply_clock =
+            if moved_piece_kind == PieceKind::Pawn || undo_record.captured_piece.is_some() {
+                0
+            } else {
+                self.min_ply_clock.saturating_add(1)
+            };
 
         self.playing_color = !self.playing_color;
+
+        self.hash ^= self.gen_state_hash();
+
+        debug_assert_eq!(
+            self.hash,
+            self.gen_hash(),
+            "incremental hash diverged from the board"
+        );
+
+        undo_record.next_hash = self.hash;
+
+        undo_record
     }
 
-    pub fn make_move(&mut self, chess_move: ChessMove) -> Result<(), MakeMoveError> {
+    // INVARIANT: The passed record must have been returned by the last move made on this board.
+    unsafe fn unmake_move_unchecked(&mut self, undo_record: UndoRecord) {
+        let UndoRecord {
+            chess_move,
+            moved_piece_kind,
+            captured_piece,
+            castling_rook_move,
+            ..
+        } = undo_record;
+
+        self.playing_color = !self.playing_color;
+        self.full_moves -= (self.playing_color == Color::Black) as u16;
+
+        mem::swap(&mut self.us, &mut self.them);
+
+        // SAFETY: The record is assumed to describe the last move made.
+        unsafe {
+            if let Some((rook_origin, rook_target)) = castling_rook_move {
+                let (king_target, _) = castling_targets(chess_move.origin, rook_origin);
+
+                self.remove_piece_unchecked(
+                    king_target,
+                    Piece {
+                        kind: PieceKind::King,
+                        color: self.playing_color,
+                    },
+                );
+
+                self.remove_piece_unchecked(
+                    rook_target,
+                    Piece {
+                        kind: PieceKind::Rook,
+                        color: self.playing_color,
+                    },
+                );
+
+                self.add_piece_unchecked(
+                    rook_origin,
+                    Piece {
+                        kind: PieceKind::Rook,
+                        color: self.playing_color,
+                    },
+                );
+            } else {
+                self.remove_piece_unchecked(
+                    chess_move.target,
+                    Piece {
+                        kind: chess_move.promotion.unwrap_or(moved_piece_kind),
+                        color: self.playing_color,
+                    },
+                );
+
+                if let Some((square, kind)) = captured_piece {
+                    self.add_piece_unchecked(
+                        square,
+                        Piece {
+                            kind,
+                            color: !self.playing_color,
+                        },
+                    );
+                }
+            }
+
+            self.add_piece_unchecked(
+                chess_move.origin,
+                Piece {
+                    kind: moved_piece_kind,
+                    color: self.playing_color,
+                },
+            );
+        }
+
+        self.us.castling_rights = undo_record.us_castling_rights;
+        self.them.castling_rights = undo_record.them_castling_rights;
+
+        self.checkers = undo_record.checkers;
+        self.pinned = undo_record.pinned;
+        self.en_passant_capture_square = undo_record.en_passant_capture_square;
+        self.min_ply_clock = undo_record.min_ply_clock;
+        self.hash = undo_record.hash;
+
+        debug_assert_eq!(
+            self.hash,
+            self.gen_hash(),
+            "unmade board doesn't match its hash"
+        );
+    }
+
+    /// Generates the part of the Zobrist hash of the board that isn't determined by the pieces on
+    /// it: the side to move, the en passant file and the castling rights of both players.
+    fn gen_state_hash(&self) -> u64 {
+        zobrist::side(self.playing_color)
+            ^ self
+                .en_passant_capture_square
+                .map_or(0, |square| zobrist::en_passant_file(square.file()))
+            ^ zobrist::castling_rights(&self.us.castling_rights, self.playing_color)
+            ^ zobrist::castling_rights(&self.them.castling_rights, !self.playing_color)
+    }
+
+    /// Generates the Zobrist hash of the board from scratch. Normally, [`Board::hash`] is updated
+    /// incrementally with every move made, and should always be equal to the value returned here.
+    pub fn gen_hash(&self) -> u64 {
+        zobrist::piece_table(&self.piece_board()) ^ self.gen_state_hash()
+    }
+
+    /// Makes the passed move on the board, if it is legal. The returned record can be passed to
+    /// [`Board::unmake_move`] to take the move back.
+    pub fn make_move(&mut self, chess_move: ChessMove) -> Result<UndoRecord, MakeMoveError> {
         if mg::gen_moves(self).contains(&chess_move) {
             // SAFETY: Move was generated for this board by the legal move generator
+            Ok(unsafe { self.make_move_unchecked(chess_move) })
+        } else {
+            Err(MakeMoveError)
+        }
+    }
+
+    /// Takes back the move described by the passed record, restoring the board to the exact state
+    /// it was in before the move was made. Records whose hash doesn't match the board are rejected,
+    /// but a matching hash doesn't prove the record belongs to the last move made, so callers must
+    /// uphold the contract below.
+    ///
+    /// # Safety
+    ///
+    /// Moves must be taken back in the reverse order to which they were made, and so the record
+    /// must have been returned by the last move made on this board which wasn't taken back yet. A
+    /// record returned by another move reaching the same position, such as an earlier move of a
+    /// game which transposed back into it, doesn't describe the board, and neither does a record
+    /// whose hash collides with the board's.
+    pub unsafe fn unmake_move(&mut self, undo_record: UndoRecord) -> Result<(), UnmakeMoveError> {
+        if undo_record.next_hash == self.hash {
+            // SAFETY: The caller guarantees the record was returned by the last move made
             unsafe {
-                self.make_move_unchecked(chess_move);
+                self.unmake_move_unchecked(undo_record);
             }
 
             Ok(())
         } else {
-            Err(MakeMoveError)
+            Err(UnmakeMoveError)
+        }
+    }
+
+    /// Converts a castle written as the king moving to its destination square, as in `e1g1`, to the
+    /// king-takes-rook encoding used by the board. This is how castles are written in standard
+    /// chess protocols, and so moves received from them should pass through here. Other moves are
+    /// returned unchanged.
+    pub fn castle_from_king_target(&self, chess_move: ChessMove) -> ChessMove {
+        let is_king_target_castle = self.us.king.get_bit(chess_move.origin)
+            && chess_move.origin.rank() == chess_move.target.rank()
+            && chess_move.origin.file().abs_diff(chess_move.target.file()) == 2
+            && !self.us.rooks.get_bit(chess_move.target);
+
+        let rook_square = if chess_move.target.file() > chess_move.origin.file() {
+            self.us.castling_rights.king_side
+        } else {
+            self.us.castling_rights.queen_side
+        };
+
+        match rook_square {
+            // Only moves landing the king where castling puts it are castles
+            Some(rook_square)
+                if is_king_target_castle
+                    && castling_targets(chess_move.origin, rook_square).0 == chess_move.target =>
+            {
+                ChessMove {
+                    target: rook_square,
+                    ..chess_move
+                }
+            }
+            _ => chess_move,
+        }
+    }
+
+    /// The inverse of [`Board::castle_from_king_target`], converting castles in which the king
+    /// moves two files to be written with the king's destination square. Castles in which the king
+    /// moves less than that are only possible in Chess960, and would be ambiguous when written
+    /// this way, so they are returned unchanged, like any other move.
+    pub fn castle_to_king_target(&self, chess_move: ChessMove) -> ChessMove {
+        if !self.us.king.get_bit(chess_move.origin) || !self.us.rooks.get_bit(chess_move.target) {
+            return chess_move;
+        }
+
+        let (king_target, _) = castling_targets(chess_move.origin, chess_move.target);
+
+        if chess_move.origin.file().abs_diff(king_target.file()) == 2 {
+            ChessMove {
+                target: king_target,
+                ..chess_move
+            }
+        } else {
+            chess_move
         }
     }
 
@@ -306,23 +607,51 @@ impl Board {
     }
 
     pub fn perft(&self, depth: u32) -> u64 {
+        let mut board = *self;
+
+        board.perft_in_place(depth, None)
+    }
+
+    pub(crate) fn perft_in_place(&mut self, depth: u32, table: Option<&PerftTable>) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        // Results at a depth of one are cheaper to count than to look up
+        if depth > 1 {
+            if let Some(nodes) = table.and_then(|table| table.get(self.hash, depth)) {
+                return nodes;
+            }
+        }
+
         let moves = mg::gen_moves(self);
 
-        match depth {
-            0 => 1,
-            // At a depth of one we know all next moves will reach depth zero.
-            // Thus, we can know they are all leaves and add one each to the nodes searched.
-            1 => moves.len() as u64,
-            _ => moves
-                .into_iter()
-                .map(|chess_move| {
-                    let mut new_board = *self;
-                    new_board.make_move(chess_move).unwrap();
-
-                    new_board.perft(depth - 1)
-                })
-                .sum(),
+        // At a depth of one we know all next moves will reach depth zero.
+        // Thus, we can know they are all leaves and add one each to the nodes searched.
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+
+        let nodes = moves
+            .into_iter()
+            .map(|chess_move| {
+                // SAFETY: Move was generated for this board by the legal move generator, and is
+                // taken back right after its subtree is counted
+                unsafe {
+                    let undo_record = self.make_move_unchecked(chess_move);
+                    let nodes = self.perft_in_place(depth - 1, table);
+                    self.unmake_move_unchecked(undo_record);
+
+                    nodes
+                }
+            })
+            .sum();
+
+        if let Some(table) = table {
+            table.insert(self.hash, depth, nodes);
         }
+
+        nodes
     }
 
     pub fn gen_child_boards(&self) -> impl Iterator<Item = (ChessMove, Board)> + '_ {
@@ -346,7 +675,7 @@ pub enum ParseBoardError {
     InvalidColor,
     #[error("invalid en passant square")]
     InvalidEnPassantSquare(#[source] Option<ParseSquareError>),
-    #[error("castling rights should only contain `K`, `Q`, `k`, and `q`, and at most once")]
+    #[error("castling rights should only contain `K`, `Q`, or rook files, at most once per side")]
     InvalidCastlingRights,
     #[error("half-move clock should be a non-negative integer")]
     InvalidHalfMoveClock(#[source] ParseIntError),
@@ -413,29 +742,50 @@ impl FromStr for Board {
             }
         }
 
-        // When creating a board from FEN, the rook squares encode castling, and since
-        // CastlingRights uses the conjunction of the king and rook squares, they are assigned
-        // `true` here.
-        white.castling_rights[Square::E1] = true;
-        black.castling_rights[Square::E8] = true;
-
-        if castling_rights_string != "-" {
-            white.castling_rights[Square::H1] = castling_rights_string.contains('K');
-            white.castling_rights[Square::A1] = castling_rights_string.contains('Q');
-            black.castling_rights[Square::H8] = castling_rights_string.contains('k');
-            black.castling_rights[Square::A8] = castling_rights_string.contains('q');
-
-            // Assuming each match was singular and the part has no invalid characters,
-            // this would be the length of the part.
-            let assumed_length = (white.castling_rights[Square::H1] as usize)
-                + (white.castling_rights[Square::A1] as usize)
-                + (black.castling_rights[Square::H8] as usize)
-                + (black.castling_rights[Square::A8] as usize);
-
-            // If the assumed length is not the actual one, the part has some invalid
-            // characters, or repetitions
-            if castling_rights_string.is_empty() || assumed_length != castling_rights_string.len() {
-                return Err(ParseBoardError::InvalidCastlingRights);
+        // Besides `KQkq`, both Shredder-FEN castling rights, which contain the files of the rooks
+        // (`HAha`), and X-FEN castling rights, which only contain the files of rooks that aren't
+        // the outermost on their side, are accepted.
+        if castling_rights_string.is_empty() {
+            return Err(ParseBoardError::InvalidCastlingRights);
+        } else if castling_rights_string != "-" {
+            for right in castling_rights_string.chars() {
+                let (player, back_rank) = if right.is_ascii_uppercase() {
+                    (&mut white, Square::RANK_1)
+                } else {
+                    (&mut black, Square::RANK_8)
+                };
+
+                let king_square = Square::try_from(player.king)
+                    .ok()
+                    .filter(|square| square.rank() == back_rank)
+                    .ok_or(ParseBoardError::InvalidCastlingRights)?;
+
+                let mut rook_squares = player
+                    .rooks
+                    .bits()
+                    .filter(|square| square.rank() == back_rank);
+
+                let rook_square = match right.to_ascii_lowercase() {
+                    'k' => rook_squares
+                        .filter(|square| square.file() > king_square.file())
+                        .last(),
+                    'q' => rook_squares.find(|square| square.file() < king_square.file()),
+                    file @ 'a'..='h' => {
+                        rook_squares.find(|square| square.file() == file as u8 - b'a')
+                    }
+                    _ => None,
+                }
+                .ok_or(ParseBoardError::InvalidCastlingRights)?;
+
+                let side_right = if rook_square.file() > king_square.file() {
+                    &mut player.castling_rights.king_side
+                } else {
+                    &mut player.castling_rights.queen_side
+                };
+
+                if side_right.replace(rook_square).is_some() {
+                    return Err(ParseBoardError::InvalidCastlingRights);
+                }
             }
         }
 
@@ -450,12 +800,7 @@ impl FromStr for Board {
             playing_color: current_color,
             piece_kind_board: piece_board.uncolored(),
             en_passant_capture_square,
-            hash: zobrist::piece_table(&piece_board)
-                ^ zobrist::side(current_color)
-                ^ en_passant_capture_square
-                    .map_or(0, |square| zobrist::en_passant_file(square.file()))
-                ^ zobrist::castling_rights(&white.castling_rights)
-                ^ zobrist::castling_rights(&black.castling_rights),
+            hash: 0,
             checkers: BitBoard::EMPTY,
             pinned: BitBoard::EMPTY,
             min_ply_clock: ply_clock,
@@ -490,29 +835,49 @@ impl FromStr for Board {
             return Err(ParseBoardError::CapturableKing);
         }
 
+        board.hash = board.gen_hash();
         board.update_move_restrictions();
 
         Ok(board)
     }
 }
 
-fn gen_castling_string(white: Player, black: Player) -> String {
+// Castling rights are written in X-FEN, unless `is_shredder` is set, in which case they are
+// written in Shredder-FEN. Both are equivalent to regular FEN for standard chess positions.
+fn gen_castling_string(white: Player, black: Player, is_shredder: bool) -> String {
     let mut castling_string = String::new();
 
-    if white.castling_rights.can_castle_king_side() {
-        castling_string.push('K');
-    }
+    for (player, color) in [(white, Color::White), (black, Color::Black)] {
+        let sides = [
+            (player.castling_rights.king_side, 'K'),
+            (player.castling_rights.queen_side, 'Q'),
+        ];
 
-    if white.castling_rights.can_castle_queen_side() {
-        castling_string.push('Q');
-    }
+        for (rook_square, side_char) in sides {
+            let Some(rook_square) = rook_square else {
+                continue;
+            };
 
-    if black.castling_rights.can_castle_king_side() {
-        castling_string.push('k');
-    }
+            let is_outermost = player
+                .rooks
+                .bits()
+                .filter(|square| square.rank() == rook_square.rank())
+                .all(|square| match side_char {
+                    'K' => square.file() <= rook_square.file(),
+                    _ => square.file() >= rook_square.file(),
+                });
 
-    if black.castling_rights.can_castle_queen_side() {
-        castling_string.push('q');
+            let right = if is_outermost && !is_shredder {
+                side_char
+            } else {
+                (b'A' + rook_square.file()) as char
+            };
+
+            castling_string.push(match color {
+                Color::White => right,
+                Color::Black => right.to_ascii_lowercase(),
+            });
+        }
     }
 
     if castling_string.is_empty() {
@@ -522,6 +887,8 @@ fn gen_castling_string(white: Player, black: Player) -> String {
     castling_string
 }
 
+/// Formats the board as FEN, with X-FEN castling rights. The alternate flag (`{:#}`) formats the
+/// castling rights in Shredder-FEN instead.
 impl Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let (white, black) = match self.playing_color {
@@ -540,7 +907,7 @@ impl Display for Board {
             "{} {} {} {} {} {}",
             self.piece_board(),
             self.playing_color,
-            gen_castling_string(white, black),
+            gen_castling_string(white, black, f.alternate()),
             en_passant_capture_square_string,
             self.min_ply_clock,
             self.full_moves
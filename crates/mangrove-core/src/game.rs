@@ -8,47 +8,152 @@ use crate::{
     repr::ChessMove,
 };
 
+/// The number of plies without captures or pawn moves after which a draw may be claimed.
+pub const FIFTY_MOVE_RULE_PLIES: u8 = 100;
+
+/// The number of plies without captures or pawn moves after which the game is automatically drawn.
+const SEVENTY_FIVE_MOVE_RULE_PLIES: u8 = 150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The reason a game ended in a draw, as per the FIDE laws of Chess.
+pub enum DrawReason {
+    /// The player to move has no legal moves, but isn't in check.
+    Stalemate,
+    /// The current position has occurred at least three times, with the same player to move.
+    ThreefoldRepetition,
+    /// The current position has occurred at least five times, with the same player to move.
+    FivefoldRepetition,
+    /// The last fifty moves of each player contained no captures or pawn moves.
+    FiftyMoveRule,
+    /// The last seventy-five moves of each player contained no captures or pawn moves.
+    SeventyFiveMoveRule,
+    /// Neither player has the material needed to checkmate the other.
+    InsufficientMaterial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The outcome of a finished game.
 pub enum Outcome {
+    /// The passed color has checkmated its opponent.
     Win(Color),
-    Draw,
+    Draw(DrawReason),
+}
+
+/// Returns the number of times the passed board has occurred in a game, including the current
+/// occurrence. The hashes are those of every position reached in the game, in order, ending with
+/// the hash of the board itself. Only the positions since the last capture or pawn move are needed.
+pub fn repetitions(board: &Board, hashes: &[u64]) -> usize {
+    // Positions from before the last capture or pawn move can never repeat, and positions with the
+    // other player to move never match, so only every other reversible ply is checked.
+    hashes
+        .iter()
+        .rev()
+        .take(board.min_ply_clock as usize + 1)
+        .step_by(2)
+        .filter(|&&hash| hash == board.hash)
+        .count()
+}
+
+/// Returns the outcome of a game which reached the passed board through positions with the passed
+/// hashes, as in [`repetitions`], if the game has ended. Draws which under FIDE rules must be
+/// claimed by a player, such as the threefold repetition, are considered to end the game.
+/// Checkmate takes precedence over the move-count rules, as a mating move ends the game
+/// immediately.
+pub fn outcome(board: &Board, hashes: &[u64]) -> Option<Outcome> {
+    if mg::gen_moves(board).is_empty() {
+        return Some(if board.in_check() {
+            Outcome::Win(!board.playing_color)
+        } else {
+            Outcome::Draw(DrawReason::Stalemate)
+        });
+    }
+
+    let repetitions = repetitions(board, hashes);
+
+    let draw_reason = if repetitions >= 5 {
+        DrawReason::FivefoldRepetition
+    } else if board.min_ply_clock >= SEVENTY_FIVE_MOVE_RULE_PLIES {
+        DrawReason::SeventyFiveMoveRule
+    } else if board.has_insufficient_material() {
+        DrawReason::InsufficientMaterial
+    } else if repetitions >= 3 {
+        DrawReason::ThreefoldRepetition
+    } else if board.min_ply_clock >= FIFTY_MOVE_RULE_PLIES {
+        DrawReason::FiftyMoveRule
+    } else {
+        return None;
+    };
+
+    Some(Outcome::Draw(draw_reason))
 }
 
 pub struct Game {
+    starting_board: Board,
     board: Board,
+    moves: Vec<ChessMove>,
+    // The hashes of every position reached in the game, including the current one, in order.
+    hashes: Vec<u64>,
 }
 
 impl Game {
-    pub fn starting_position() -> Self {
+    /// Creates a new game, starting from the passed board.
+    pub fn new(board: Board) -> Self {
         Self {
-            board: Board::starting_position(),
+            starting_board: board,
+            board,
+            moves: Vec::new(),
+            hashes: vec![board.hash],
         }
     }
 
+    pub fn starting_position() -> Self {
+        Self::new(Board::starting_position())
+    }
+
+    /// Returns the number of times the current position has occurred in the game, including the
+    /// current occurrence.
+    pub fn repetitions(&self) -> usize {
+        repetitions(&self.board, &self.hashes)
+    }
+
+    /// Returns the outcome of the game, if it has ended, as per [`outcome`].
     pub fn outcome(&self) -> Option<Outcome> {
-        if mg::gen_moves(&self.board).is_empty() {
-            Some(if self.board.in_check() {
-                Outcome::Win(!self.board.playing_color)
-            } else {
-                Outcome::Draw
-            })
-        } else {
-            None
-        }
+        outcome(&self.board, &self.hashes)
     }
 
     pub fn make_move(&mut self, chess_move: ChessMove) -> Result<(), MakeMoveError> {
-        self.board.make_move(chess_move)
+        self.board.make_move(chess_move)?;
+        self.moves.push(chess_move);
+        self.hashes.push(self.board.hash);
+
+        Ok(())
     }
 
     pub fn board(&self) -> &Board {
         &self.board
     }
+
+    /// Returns the board the game started from, before any of its moves were made.
+    pub fn starting_board(&self) -> &Board {
+        &self.starting_board
+    }
+
+    /// Returns every move made in the game, in order.
+    pub fn moves(&self) -> &[ChessMove] {
+        &self.moves
+    }
+
+    /// Returns the hashes of every position reached in the game so far, from the first position to
+    /// the current one.
+    pub fn hashes(&self) -> &[u64] {
+        &self.hashes
+    }
 }
 
 impl FromStr for Game {
     type Err = ParseBoardError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Board::from_str(s).map(|board| Self { board })
+        Board::from_str(s).map(Self::new)
     }
 }
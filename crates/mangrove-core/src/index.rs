@@ -286,16 +286,27 @@ pub mod zobrist {
         ZOBRIST_MAP.ep_file[file as usize]
     }
 
-    /// Generates the Zobrist hash-core for the castling rights of a board, to distinguish boards
-    /// based on this.
-    pub fn castling_rights(castling_rights: &CastlingRights) -> u64 {
-        // SAFETY: The structure of CastlingRights is known
-        *unsafe {
-            ZOBRIST_MAP
-                .castling_rights
-                .0
-                .get_unchecked(castling_rights.as_minimized_rights())
-        }
+    /// Generates the Zobrist hash-core for the castling rights of a player of the passed color, to
+    /// distinguish boards based on this. Each right is hashed with the file of the rook it castles
+    /// with, which differs between Chess960 positions.
+    pub fn castling_rights(castling_rights: &CastlingRights, color: Color) -> u64 {
+        let (king_side, queen_side) = match color {
+            Color::White => (
+                &ZOBRIST_MAP.castling_rights.white_king_side,
+                &ZOBRIST_MAP.castling_rights.white_queen_side,
+            ),
+            Color::Black => (
+                &ZOBRIST_MAP.castling_rights.black_king_side,
+                &ZOBRIST_MAP.castling_rights.black_queen_side,
+            ),
+        };
+
+        castling_rights
+            .king_side
+            .map_or(0, |rook_square| king_side[rook_square.file() as usize])
+            ^ castling_rights
+                .queen_side
+                .map_or(0, |rook_square| queen_side[rook_square.file() as usize])
     }
 
     /// Generates the Zobrist hash-core for a piece at a given square. Used in [`zobrist::piece_table`].
@@ -2,13 +2,24 @@ pub mod board;
 pub mod game;
 mod index;
 pub mod mg;
+pub mod perft;
+pub mod pgn;
 pub mod repr;
+pub mod san;
 
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
-    use crate::{board::Board, repr::ChessMove};
+    use crate::{
+        board::Board,
+        game::{DrawReason, Game, Outcome},
+        mg,
+        perft::PerftTable,
+        pgn::{GameResult, ParsePgnErrorKind, PgnGame, PgnReader},
+        repr::ChessMove,
+    };
+    use mangrove_bootstrap::Color;
     use test_case::test_case;
 
     #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"; "starting position")]
@@ -20,6 +31,8 @@ mod tests {
     #[test_case("rnbqkbnr/ppp1pppp/8/8/1PPpP3/8/P2P1PPP/RNBQKBNR b KQkq c3 0 3"; "en passant test")]
     #[test_case("r1bq1b1r/ppppk1pp/2n2n2/4pp2/2B1PP2/5N2/PPPP2PP/RNBQ1RK1 w - - 6 6"; "no castling test")]
     #[test_case("1nbqkbnr/1ppppppp/r7/p7/7P/7R/PPPPPPP1/RNBQKBN1 w Qk - 2 3"; "partial castling test")]
+    #[test_case("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"; "chess960 castling test")]
+    #[test_case("1r2k1r1/8/8/8/8/8/8/RR2K1RR w Bq - 0 1"; "x-fen castling test")]
     fn circular_fen_tests(fen_string: &str) {
         assert_eq!(
             fen_string,
@@ -27,6 +40,20 @@ mod tests {
         );
     }
 
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1", "KQkq", "HAha"; "standard shredder")]
+    #[test_case("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", "KQkq", "HFhf"; "chess960 shredder")]
+    #[test_case("1r2k1r1/8/8/8/8/8/8/RR2K1RR w GBgb - 0 1", "GBkq", "GBgb"; "inner rooks shredder")]
+    #[test_case("1r2k1r1/8/8/8/8/8/8/RR2K1RR w KQkq - 0 1", "KQkq", "HAgb"; "outer rooks x-fen")]
+    fn castling_notation_tests(fen_string: &str, x_fen_rights: &str, shredder_fen_rights: &str) {
+        let board = Board::from_str(fen_string).unwrap();
+
+        assert_eq!(board.to_string().split(' ').nth(2), Some(x_fen_rights));
+        assert_eq!(
+            format!("{board:#}").split(' ').nth(2),
+            Some(shredder_fen_rights)
+        );
+    }
+
     #[should_panic]
     #[test_case("rnbqkbnr/pppppppp/8/8/9/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"; "invalid row spacing 1")]
     #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PP3PPPPP/RNBQKBNR w KQkq - 0 1"; "invalid row spacing 2")]
@@ -36,6 +63,9 @@ mod tests {
     #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w  - 0 1"; "invalid castling information 1")]
     #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KK - 0 1"; "invalid castling information 2")]
     #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KW - 0 1"; "invalid castling information 3")]
+    #[test_case("rnbq1bnr/ppppkppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"; "castling with moved king")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w K - 0 1"; "castling without rook")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HK - 0 1"; "castling twice on one side")]
     #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq aa 0 1"; "invalid en passant square")]
     #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1"; "illegal en passant square")]
     #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - a 1"; "invalid half move clock")]
@@ -78,6 +108,16 @@ mod tests {
     #[test_case("r3k2r/p1pp1pb1/bn2Qnp1/2qPN3/1p2P3/2N5/PPPBBPPP/R3K2R b KQkq - 3 2", 1, 5; "misc 4 depth 1")]
     #[test_case("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 3, 62379; "misc 5 depth 3")]
     #[test_case("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", 3, 89890; "misc 6 depth 3")]
+    #[test_case("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 5, 8146062; "chess960 1 depth 5")]
+    #[test_case("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", 5, 16253601; "chess960 2 depth 5")]
+    #[test_case("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", 5, 6417013; "chess960 3 depth 5")]
+    #[test_case("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", 5, 9183776; "chess960 4 depth 5")]
+    #[test_case("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", 4, 1171749; "chess960 5 depth 4")]
+    #[test_case("qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9", 4, 824055; "chess960 6 depth 4")]
+    #[test_case("q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9", 4, 732757; "chess960 7 depth 4")]
+    #[test_case("qbn1brkr/ppp1p1p1/2n4p/3p1p2/P7/6PP/QPPPPP2/1BNNBRKR w HFhf - 0 9", 4, 465806; "chess960 8 depth 4")]
+    #[test_case("qn1rbbkr/ppp2p1p/1n1pp1p1/8/3P4/P6P/1PP1PPPK/QNNRBB1R w hd - 2 9", 4, 679699; "chess960 9 depth 4")]
+    #[test_case("1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9", 5, 8652810; "chess960 10 depth 5")]
     fn perft_tests(position_fen: &str, depth: u32, expected_result: u64) {
         assert_eq!(
             Board::from_str(position_fen).unwrap().perft(depth),
@@ -85,6 +125,419 @@ mod tests {
         );
     }
 
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 4; "starting position depth 4")]
+    #[test_case("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3; "kiwipete depth 3")]
+    #[test_case("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 5; "en passant move with check depth 5")]
+    #[test_case("1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9", 3; "chess960 depth 3")]
+    #[test_case("K1k5/8/P7/8/8/8/8/8 w - - 0 1", 6; "self stalemate depth 6")]
+    fn parallel_perft_tests(position_fen: &str, depth: u32) {
+        let board = Board::from_str(position_fen).unwrap();
+        let expected_result = board.perft(depth);
+
+        for threads in [1, 3, 8] {
+            assert_eq!(board.parallel_perft(depth, threads, None), expected_result);
+
+            // A tiny table forces constant replacement of entries
+            for table_size in [1 << 10, 1 << 20] {
+                assert_eq!(
+                    board.parallel_perft(depth, threads, Some(&PerftTable::new(table_size))),
+                    expected_result
+                );
+            }
+        }
+    }
+
+    fn assert_hashes(board: &Board, depth: u32) {
+        assert_eq!(board.hash, board.gen_hash(), "hash mismatch for {board}");
+
+        if depth != 0 {
+            for (_, child_board) in board.gen_child_boards() {
+                assert_hashes(&child_board, depth - 1);
+            }
+        }
+    }
+
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 4; "starting position depth 4")]
+    #[test_case("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3; "kiwipete depth 3")]
+    #[test_case("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 4; "en passant move with check depth 4")]
+    #[test_case("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", 3; "castle rights depth 3")]
+    #[test_case("1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9", 3; "chess960 depth 3")]
+    #[test_case("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", 4; "promotion out of check depth 4")]
+    #[test_case("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 3; "misc 5 depth 3")]
+    fn incremental_hash_tests(position_fen: &str, depth: u32) {
+        assert_hashes(&Board::from_str(position_fen).unwrap(), depth);
+    }
+
+    #[test_case("4k3/8/8/8/8/8/8/RR2K3 w A - 0 1", "4k3/8/8/8/8/8/8/RR2K3 w B - 0 1"; "queen-side rooks")]
+    #[test_case("r3k1rr/8/8/8/8/8/8/4K3 b g - 0 1", "r3k1rr/8/8/8/8/8/8/4K3 b h - 0 1"; "black king-side rooks")]
+    fn chess960_castling_hash_tests(position_fen: &str, other_position_fen: &str) {
+        let board = Board::from_str(position_fen).unwrap();
+        let other_board = Board::from_str(other_position_fen).unwrap();
+
+        // The boards only differ in which rook holds the castling right
+        assert_eq!(
+            board.to_string().split(' ').next(),
+            other_board.to_string().split(' ').next()
+        );
+        assert_ne!(board.hash, other_board.hash);
+    }
+
+    fn assert_unmake(board: &mut Board, depth: u32) {
+        if depth == 0 {
+            return;
+        }
+
+        let original_board = *board;
+
+        for chess_move in mg::gen_moves(board) {
+            let undo_record = board.make_move(chess_move).unwrap();
+            assert_unmake(board, depth - 1);
+            // SAFETY: The record was returned by the last move made, whose subtree was taken back
+            unsafe { board.unmake_move(undo_record) }.unwrap();
+
+            assert_eq!(*board, original_board, "failed to unmake {chess_move}");
+        }
+    }
+
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 4; "starting position depth 4")]
+    #[test_case("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3; "kiwipete depth 3")]
+    #[test_case("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 4; "en passant move with check depth 4")]
+    #[test_case("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", 3; "castle rights depth 3")]
+    #[test_case("1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9", 3; "chess960 depth 3")]
+    #[test_case("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", 4; "promotion out of check depth 4")]
+    #[test_case("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 3; "misc 5 depth 3")]
+    fn unmake_move_tests(position_fen: &str, depth: u32) {
+        assert_unmake(&mut Board::from_str(position_fen).unwrap(), depth);
+    }
+
+    #[test_case("4k3/8/8/8/8/8/8/1RK5 w B - 0 1", "c1b1", Some("4k3/8/8/8/8/8/8/2KR4 b - - 1 1"); "king staying in place")]
+    #[test_case("4k3/8/8/8/8/8/8/4KR2 w F - 0 1", "e1f1", Some("4k3/8/8/8/8/8/8/5RK1 b - - 1 1"); "rook staying in place")]
+    #[test_case("4k3/8/8/8/8/8/8/RK5R w HA - 0 1", "b1h1", Some("4k3/8/8/8/8/8/8/R4RK1 b - - 1 1"); "king passing the rook")]
+    #[test_case("4k3/8/8/8/8/8/8/rRK5 w B - 0 1", "c1b1", None; "shielded target square")]
+    #[test_case("4k3/8/8/8/8/8/8/RK3b1R w HA - 0 1", "b1h1", None; "blocked king path")]
+    fn chess960_castle_tests(position_fen: &str, chess_move: &str, expected_fen: Option<&str>) {
+        let mut board = Board::from_str(position_fen).unwrap();
+        let result = board.make_move(ChessMove::from_str(chess_move).unwrap());
+
+        assert_eq!(result.is_ok(), expected_fen.is_some());
+
+        if let Some(expected_fen) = expected_fen {
+            assert_eq!(board.to_string(), expected_fen);
+        }
+    }
+
+    #[test_case("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1", "e1h1"; "king-side castle")]
+    #[test_case("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8", "e8a8"; "queen-side castle")]
+    #[test_case("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1f1", "e1f1"; "regular king move")]
+    #[test_case("4k3/8/8/8/8/8/8/1R2K3 w B - 0 1", "e1c1", "e1b1"; "chess960 castle")]
+    #[test_case("4k3/8/8/8/8/8/8/1RK5 w B - 0 1", "c1b1", "c1b1"; "chess960 castle without king movement")]
+    #[test_case("4k3/8/8/8/8/8/8/3K2R1 w G - 0 1", "d1f1", "d1f1"; "chess960 king move short of castling")]
+    #[test_case("4k3/8/8/8/8/8/8/3K3R w H - 0 1", "d1f1", "d1f1"; "chess960 king move short of castling with corner rook")]
+    fn king_target_castle_tests(
+        position_fen: &str,
+        king_target_move: &str,
+        king_takes_rook_move: &str,
+    ) {
+        let board = Board::from_str(position_fen).unwrap();
+        let king_target_move = ChessMove::from_str(king_target_move).unwrap();
+        let king_takes_rook_move = ChessMove::from_str(king_takes_rook_move).unwrap();
+
+        assert_eq!(
+            board.castle_from_king_target(king_target_move),
+            king_takes_rook_move
+        );
+        assert_eq!(
+            board.castle_to_king_target(king_takes_rook_move),
+            king_target_move
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_unmake_move() {
+        let mut board = Board::starting_position();
+        let undo_record = board
+            .make_move(ChessMove::from_str("e2e4").unwrap())
+            .unwrap();
+
+        board
+            .make_move(ChessMove::from_str("e7e5").unwrap())
+            .unwrap();
+        // SAFETY: The record isn't the last one, but its hash doesn't match the board, so it is
+        // rejected before it is used
+        unsafe { board.unmake_move(undo_record) }.unwrap();
+    }
+
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 0; "starting position")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1", 1; "black to move")]
+    #[test_case("8/8/4k3/8/8/3K4/8/R7 w - - 0 40", 78; "later move")]
+    fn ply_tests(position_fen: &str, expected_ply: u32) {
+        assert_eq!(Board::from_str(position_fen).unwrap().ply(), expected_ply);
+    }
+
+    #[test]
+    fn transposition_hash() {
+        let mut board = Board::starting_position();
+
+        for chess_move in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            board
+                .make_move(ChessMove::from_str(chess_move).unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(board.hash, Board::starting_position().hash);
+    }
+
+    fn play_moves(game: &mut Game, moves: &[&str]) {
+        for chess_move in moves {
+            game.make_move(ChessMove::from_str(chess_move).unwrap())
+                .unwrap();
+        }
+    }
+
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &["f2f3", "e7e5", "g2g4", "d8h4"], Some(Outcome::Win(Color::Black)); "fool's mate")]
+    #[test_case("k7/8/1QK5/8/8/8/8/8 w - - 0 1", &["b6b5", "a8a7", "b5b6"], None; "no outcome")]
+    #[test_case("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", &[], Some(Outcome::Draw(DrawReason::Stalemate)); "stalemate")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"], None; "double repetition")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8"], Some(Outcome::Draw(DrawReason::ThreefoldRepetition)); "threefold repetition")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8"], Some(Outcome::Draw(DrawReason::FivefoldRepetition)); "fivefold repetition")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &["e2e4", "e7e5", "e1e2", "e8e7", "e2e1", "e7e8", "e1e2", "e8e7", "e2e1", "e7e8"], None; "lost castling rights")]
+    #[test_case("4k3/8/8/8/8/8/4P3/R3K3 w - - 98 80", &["a1a2"], None; "before fifty move rule")]
+    #[test_case("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80", &["a1a2"], Some(Outcome::Draw(DrawReason::FiftyMoveRule)); "fifty move rule")]
+    #[test_case("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80", &["e2e4"], None; "fifty move rule reset")]
+    #[test_case("4k3/8/8/8/8/8/4P3/R3K3 w - - 149 80", &["a1a2"], Some(Outcome::Draw(DrawReason::SeventyFiveMoveRule)); "seventy-five move rule")]
+    #[test_case("6k1/5ppp/8/8/8/8/8/R3K3 w - - 99 80", &["a1a8"], Some(Outcome::Win(Color::White)); "checkmate over fifty move rule")]
+    #[test_case("4k3/8/8/8/8/8/8/4K3 w - - 0 1", &[], Some(Outcome::Draw(DrawReason::InsufficientMaterial)); "bare kings")]
+    #[test_case("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", &[], Some(Outcome::Draw(DrawReason::InsufficientMaterial)); "single knight")]
+    #[test_case("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", &[], Some(Outcome::Draw(DrawReason::InsufficientMaterial)); "same colored bishops")]
+    #[test_case("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1", &[], None; "opposite colored bishops")]
+    #[test_case("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1", &[], None; "two knights")]
+    #[test_case("4k3/8/8/8/8/8/8/4K1NR w - - 0 1", &["h1h8", "e8d7", "h8h1"], None; "enough material")]
+    fn outcome_tests(position_fen: &str, moves: &[&str], expected_outcome: Option<Outcome>) {
+        let mut game = Game::from_str(position_fen).unwrap();
+        play_moves(&mut game, moves);
+
+        assert_eq!(game.outcome(), expected_outcome);
+    }
+
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "g1f3", "Nf3"; "knight move")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e2e4", "e4"; "pawn push")]
+    #[test_case("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", "e4d5", "exd5"; "pawn capture")]
+    #[test_case("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", "e5f6", "exf6"; "en passant")]
+    #[test_case("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", "e1h1", "O-O"; "king-side castle")]
+    #[test_case("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1", "e8a8", "O-O-O"; "queen-side castle")]
+    #[test_case("4k3/8/8/8/8/8/8/RK5R w HA - 0 1", "b1h1", "O-O"; "chess960 king-side castle")]
+    #[test_case("4k3/8/8/8/8/8/8/RK5R w HA - 0 1", "b1a1", "O-O-O"; "chess960 queen-side castle")]
+    #[test_case("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2", "d8h4", "Qh4#"; "checkmate")]
+    #[test_case("4k3/8/8/8/8/8/8/R3K2R w - - 0 1", "a1a8", "Ra8+"; "check")]
+    #[test_case("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1", "a1d1", "Rad1"; "file disambiguation")]
+    #[test_case("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3", "R1a3"; "rank disambiguation")]
+    #[test_case("k7/8/8/8/8/2Q1Q3/8/4Q2K w - - 0 1", "e3d2", "Qe3d2"; "square disambiguation")]
+    #[test_case("6k1/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8q", "e8=Q+"; "promotion with check")]
+    #[test_case("3r2k1/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7d8n", "exd8=N"; "capture promotion")]
+    #[test_case("4k3/8/8/b7/8/2N3N1/8/4K3 w - - 0 1", "g3e2", "Ne2"; "pinned rival")]
+    fn san_tests(position_fen: &str, chess_move: &str, san: &str) {
+        let board = Board::from_str(position_fen).unwrap();
+        let chess_move = ChessMove::from_str(chess_move).unwrap();
+
+        assert_eq!(chess_move.to_san(&board).unwrap(), san);
+        assert_eq!(ChessMove::from_san(san, &board).unwrap(), chess_move);
+    }
+
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "Ng1-f3", "g1f3"; "hyphenated origin")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e2e4", "e2e4"; "long algebraic")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "Nf3!?", "g1f3"; "annotated")]
+    #[test_case("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", "exf6 e.p.", "e5f6"; "en passant suffix")]
+    #[test_case("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", "0-0", "e1h1"; "zero castle")]
+    #[test_case("6k1/4P3/8/8/8/8/8/4K3 w - - 0 1", "e8Q", "e7e8q"; "promotion without equals sign")]
+    #[test_case("6k1/4P3/8/8/8/8/8/4K3 w - - 0 1", "e8=R", "e7e8r"; "missing check")]
+    fn lenient_san_tests(position_fen: &str, san: &str, chess_move: &str) {
+        assert_eq!(
+            ChessMove::from_san(san, &Board::from_str(position_fen).unwrap()).unwrap(),
+            ChessMove::from_str(chess_move).unwrap()
+        );
+    }
+
+    #[should_panic]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "Nf4"; "unreachable target")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "O-O"; "illegal castle")]
+    #[test_case("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1", "Rd1"; "ambiguous move")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "N"; "missing target")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "Nz9"; "invalid target")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "Ngg1f3"; "invalid disambiguation")]
+    fn invalid_san_tests(position_fen: &str, san: &str) {
+        ChessMove::from_san(san, &Board::from_str(position_fen).unwrap()).unwrap();
+    }
+
+    fn assert_san_round_trip(board: &Board, depth: u32) {
+        if depth == 0 {
+            return;
+        }
+
+        for (chess_move, child_board) in board.gen_child_boards() {
+            let san = chess_move.to_san(board).unwrap();
+
+            assert_eq!(
+                ChessMove::from_san(&san, board).unwrap(),
+                chess_move,
+                "{san}"
+            );
+            assert_san_round_trip(&child_board, depth - 1);
+        }
+    }
+
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 3; "starting position depth 3")]
+    #[test_case("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 2; "kiwipete depth 2")]
+    #[test_case("1Q6/QP6/n3n1p1/P5N1/1pp5/1p2K2p/6NN/1k6 w - - 0 1", 2; "random 4 depth 2")]
+    #[test_case("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 2; "misc 5 depth 2")]
+    fn san_round_trip_tests(position_fen: &str, depth: u32) {
+        assert_san_round_trip(&Board::from_str(position_fen).unwrap(), depth);
+    }
+
+    const PGN_STREAM: &str = r#"[Event "Casual game"]
+[Site "London \"Simpson's Divan\""]
+[Result "1-0"]
+
+% An escaped line, which shouldn't be parsed: 1. e4 {
+1. e4 e5 2. Nf3 {A comment (with parentheses)} Nc6 3. Bc4 $1 (3. Bb5 a6 (3... Nf6
+4. O-O) 4. Ba4) 3... Bc5!? ; A rest of line comment
+4. b4 Bxb4 5. c3 Ba5 6. d4 exd4 7. O-O d3 1-0
+
+[Event "Second game"]
+[SetUp "1"]
+[FEN "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3"]
+
+3... dxe3 e.p. 4. Qh5 g6 *
+"#;
+
+    #[test]
+    fn pgn_stream() {
+        let games = PgnReader::new(PGN_STREAM.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(games.len(), 2);
+
+        assert_eq!(games[0].tag("Event"), Some("Casual game"));
+        assert_eq!(games[0].tag("Site"), Some("London \"Simpson's Divan\""));
+        assert_eq!(games[0].result, GameResult::WhiteWin);
+        assert_eq!(games[0].game.moves().len(), 14);
+        assert_eq!(
+            games[0].game.board().to_string(),
+            "r1bqk1nr/pppp1ppp/2n5/b7/2B1P3/2Pp1N2/P4PPP/RNBQ1RK1 w kq - 0 8"
+        );
+
+        assert_eq!(games[1].result, GameResult::Unknown);
+        assert_eq!(games[1].game.moves().len(), 3);
+        assert_eq!(
+            games[1].game.board().to_string(),
+            "rnbqkbnr/ppp1pp1p/6p1/7Q/8/4p3/PPPP1PPP/RNB1KBNR w KQkq - 0 5"
+        );
+    }
+
+    #[test]
+    fn pgn_error_recovery() {
+        let pgn =
+            "[Event \"Broken\"]\n\n1. e4 e5\n2. Nf3 Nf6 3. Ke3 *\n\n[Event \"Fine\"]\n\n1. d4 *\n";
+        let mut reader = PgnReader::new(pgn.as_bytes());
+
+        let Some(Err(error)) = reader.next() else {
+            panic!("broken game was parsed");
+        };
+
+        assert_eq!((error.line, error.column), (4, 15));
+        assert!(matches!(error.kind, ParsePgnErrorKind::InvalidMove(ref san, _) if san == "Ke3"));
+
+        let game = reader.next().unwrap().unwrap();
+
+        assert_eq!(game.tag("Event"), Some("Fine"));
+        assert_eq!(game.game.moves().len(), 1);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn pgn_missing_result_recovery() {
+        let pgn =
+            "[Event \"Unfinished\"]\n\n1. e4 e5\n\n[Event \"Next\"]\n[Site \"Here\"]\n\n1. d4 *\n";
+        let mut reader = PgnReader::new(pgn.as_bytes());
+
+        let Some(Err(error)) = reader.next() else {
+            panic!("unfinished game was parsed");
+        };
+
+        assert_eq!((error.line, error.column), (5, 1));
+        assert!(matches!(error.kind, ParsePgnErrorKind::MissingResult));
+
+        // The tag pair the unfinished game ran into belongs to the next game
+        let game = reader.next().unwrap().unwrap();
+
+        assert_eq!(game.tag("Event"), Some("Next"));
+        assert_eq!(game.tag("Site"), Some("Here"));
+        assert_eq!(game.game.moves().len(), 1);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn pgn_variations_skipped() {
+        // The variations hold moves which are illegal in the main line, and a result of their own
+        let pgn = "1. e4 (1. d4 d5 (1... Nf6 2. c4) 2. c4 1-0) 1... e5 (1... Qxh2) 2. Nf3 *\n";
+        let game = PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+
+        assert_eq!(game.result, GameResult::Unknown);
+        assert_eq!(
+            game.game.moves(),
+            ["e2e4", "e7e5", "g1f3"].map(|chess_move| ChessMove::from_str(chess_move).unwrap())
+        );
+    }
+
+    #[test_case(""; "empty")]
+    #[test_case("1. e4 e5"; "missing result")]
+    #[test_case("[Event \"Unterminated]\n\n*"; "unterminated tag")]
+    #[test_case("1. e4 {Unterminated comment *"; "unterminated comment")]
+    #[test_case("1. e4 e5) *"; "unbalanced variation")]
+    #[test_case("[FEN \"8/8/8 w - - 0 1\"]\n\n*"; "invalid fen")]
+    #[should_panic]
+    fn invalid_pgn_tests(pgn: &str) {
+        PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+    }
+
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5c6", "d7c6", "e1h1", "f7f6", "d2d4", "e5d4", "f3d4", "c6c5", "d4b3", "d8d1", "f1d1", "c8g4", "f2f3", "g4e6", "b1c3", "g8e7", "c1e3", "e7g6"], None, None; "ruy lopez")]
+    #[test_case("6k1/5ppp/8/8/8/8/8/R3K3 w Q - 0 1", &["a1a8"], Some("6k1/5ppp/8/8/8/8/8/R3K3 w Q - 0 1"), None; "checkmate")]
+    #[test_case("4k3/8/8/8/8/8/4p3/2K5 b - - 0 40", &["e2e1q", "c1b2", "e1e7"], Some("4k3/8/8/8/8/8/4p3/2K5 b - - 0 40"), None; "black to move")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 4 5", &["e2e4", "e7e5"], Some("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 4 5"), None; "starting setup on a later move")]
+    #[test_case("bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1", &["g2g3", "g7g6", "f1g1"], Some("bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w KQkq - 0 1"), Some("Chess960"); "chess960")]
+    fn pgn_round_trip_tests(
+        position_fen: &str,
+        moves: &[&str],
+        fen_tag: Option<&str>,
+        variant_tag: Option<&str>,
+    ) {
+        let mut game = Game::new(Board::from_str(position_fen).unwrap());
+
+        for chess_move in moves {
+            game.make_move(ChessMove::from_str(chess_move).unwrap())
+                .unwrap();
+        }
+
+        let mut pgn_game = PgnGame::new(game);
+        pgn_game.set_tag("White", "Doe, \"John\"");
+
+        assert_eq!(pgn_game.tag("FEN"), fen_tag);
+        assert_eq!(pgn_game.tag("Variant"), variant_tag);
+
+        let pgn = pgn_game.to_string();
+        let parsed_game = PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+
+        assert!(pgn.lines().all(|line| line.len() < 80), "{pgn}");
+        assert_eq!(parsed_game.tags, pgn_game.tags);
+        assert_eq!(parsed_game.result, pgn_game.result);
+        assert_eq!(parsed_game.game.moves(), pgn_game.game.moves());
+        assert_eq!(
+            parsed_game.game.board().to_string(),
+            pgn_game.game.board().to_string()
+        );
+    }
+
     #[test]
     #[should_panic]
     fn invalid_make_move() {
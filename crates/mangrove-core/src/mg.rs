@@ -2,7 +2,7 @@ use arrayvec::ArrayVec;
 use mangrove_bootstrap::{BitBoard, Color, Square};
 
 use crate::{
-    board::Board,
+    board::{self, Board},
     index,
     repr::{ChessMove, PieceKind},
 };
@@ -392,42 +392,37 @@ impl Gen for King {
             }),
         );
 
-        // Castles
+        // Castles, which are encoded as the king capturing its own rook
         if !C::IN_CHECK {
-            let king_side_castle_mask = BitBoard::king_side_castle_mask(board.playing_color);
+            let rights = board.us.castling_rights;
 
-            if board.us.castling_rights.can_castle_king_side()
-                && (king_side_castle_mask & board.occupation()).is_empty()
-                && (king_side_castle_mask
-                    .bits()
-                    .all(|square| !board.is_attacked_by_them(square)))
-            {
-                moves.push(ChessMove {
-                    origin: king_square,
-                    target: match board.playing_color {
-                        Color::White => Square::G1,
-                        Color::Black => Square::G8,
-                    },
-                    promotion: None,
-                });
-            }
+            for rook_square in [rights.king_side, rights.queen_side].into_iter().flatten() {
+                let (king_target, rook_target) = board::castling_targets(king_square, rook_square);
 
-            if board.us.castling_rights.can_castle_queen_side()
-                && (BitBoard::queen_side_castle_occupation_mask(board.playing_color)
-                    & board.occupation())
-                .is_empty()
-                && (BitBoard::queen_side_castle_attack_mask(board.playing_color)
-                    .bits()
-                    .all(|square| !board.is_attacked_by_them(square)))
-            {
-                moves.push(ChessMove {
-                    origin: king_square,
-                    target: match board.playing_color {
-                        Color::White => Square::C1,
-                        Color::Black => Square::C8,
-                    },
-                    promotion: None,
-                });
+                let king_path = index::line_between(king_square, king_target) | king_target.into();
+                let rook_path = index::line_between(rook_square, rook_target) | rook_target.into();
+
+                // The king and the castling rook may pass through each other's squares
+                let blockers = board.occupation() ^ board.us.king ^ rook_square.into();
+
+                // In Chess960, the castling rook may shield the king's target square from an
+                // attack along the back rank, which is exposed once it moves.
+                let is_target_exposed = !(index::rook_slides(king_target, blockers)
+                    & (board.them.rooks | board.them.queens))
+                    .is_empty();
+
+                if ((king_path | rook_path) & blockers).is_empty()
+                    && king_path
+                        .bits()
+                        .all(|square| !board.is_attacked_by_them(square))
+                    && !is_target_exposed
+                {
+                    moves.push(ChessMove {
+                        origin: king_square,
+                        target: rook_square,
+                        promotion: None,
+                    });
+                }
             }
         }
     }
@@ -0,0 +1,108 @@
+//! Parallel perft, along with a transposition table which may be shared between the threads
+//! counting nodes, so subtrees reached through different move orders are only counted once.
+
+use std::{
+    mem,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::{board::Board, mg};
+
+struct PerftEntry {
+    // The key of the entry, XORed with its node count, so entries torn by concurrent writes are
+    // detected, rather than returning wrong counts.
+    check: AtomicU64,
+    nodes: AtomicU64,
+}
+
+/// A fixed-size table of perft results, keyed on the hash of a board and the depth it was counted
+/// to. The table is lock-free, and may be shared by any number of threads. When two results map
+/// to the same slot, the newer one replaces the older.
+pub struct PerftTable {
+    entries: Box<[PerftEntry]>,
+}
+
+impl PerftTable {
+    /// Creates an empty table using roughly the passed amount of memory, in bytes.
+    pub fn new(size: usize) -> Self {
+        let entry_count = (size / mem::size_of::<PerftEntry>()).max(1);
+
+        Self {
+            entries: (0..entry_count)
+                .map(|_| PerftEntry {
+                    check: AtomicU64::new(0),
+                    nodes: AtomicU64::new(0),
+                })
+                .collect(),
+        }
+    }
+
+    fn key(hash: u64, depth: u32) -> u64 {
+        // Spread depths across the key, so results for the same board at different depths don't
+        // share a slot.
+        hash ^ (depth as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn entry(&self, key: u64) -> &PerftEntry {
+        &self.entries[(key % self.entries.len() as u64) as usize]
+    }
+
+    pub(crate) fn get(&self, hash: u64, depth: u32) -> Option<u64> {
+        let key = Self::key(hash, depth);
+        let entry = self.entry(key);
+
+        let nodes = entry.nodes.load(Ordering::Relaxed);
+        let check = entry.check.load(Ordering::Relaxed);
+
+        (check ^ nodes == key).then_some(nodes)
+    }
+
+    pub(crate) fn insert(&self, hash: u64, depth: u32, nodes: u64) {
+        let key = Self::key(hash, depth);
+        let entry = self.entry(key);
+
+        entry.nodes.store(nodes, Ordering::Relaxed);
+        entry.check.store(key ^ nodes, Ordering::Relaxed);
+    }
+}
+
+impl Board {
+    /// Counts the same nodes as [`Board::perft`], but splits the moves of the board between the
+    /// passed number of threads, which take the next uncounted move whenever they finish one.
+    /// Results are stored in and reused from the passed table, if there is one.
+    pub fn parallel_perft(&self, depth: u32, threads: usize, table: Option<&PerftTable>) -> u64 {
+        if depth <= 1 {
+            return self.perft(depth);
+        }
+
+        let moves = mg::gen_moves(self);
+        let next_move_index = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            let workers = (0..threads.clamp(1, moves.len().max(1)))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut nodes = 0;
+
+                        while let Some(&chess_move) =
+                            moves.get(next_move_index.fetch_add(1, Ordering::Relaxed))
+                        {
+                            let mut board = *self;
+                            board.make_move(chess_move).unwrap();
+
+                            nodes += board.perft_in_place(depth - 1, table);
+                        }
+
+                        nodes
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("perft thread panicked"))
+                .sum()
+        })
+    }
+}
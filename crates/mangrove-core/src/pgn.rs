@@ -0,0 +1,526 @@
+//! Reading and writing of games in Portable Game Notation (PGN). Games are read as a stream from any
+//! [`BufRead`], so files containing many games never need to be loaded into memory at once.
+//!
+//! Only the main line of each game is kept. Comments, numeric annotation glyphs (NAGs) and
+//! variations are skipped over, and the moves of variations aren't checked, so games with
+//! variations load as if they had none.
+
+use std::{
+    fmt::{self, Display},
+    io::{self, BufRead, Lines},
+    str::FromStr,
+};
+
+use mangrove_bootstrap::{Color, Square};
+
+use crate::{
+    board::{Board, ParseBoardError},
+    game::{Game, Outcome},
+    repr::{ChessMove, Player},
+    san::ParseSanError,
+};
+
+/// The maximum length of a line of movetext written by [`PgnGame`]'s `Display` implementation, as
+/// recommended by the PGN standard.
+const MAX_LINE_LENGTH: usize = 79;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The result of a game, as written in its game termination marker.
+pub enum GameResult {
+    WhiteWin,
+    BlackWin,
+    Draw,
+    /// The game is still ongoing, was abandoned, or its result is otherwise unknown.
+    Unknown,
+}
+
+impl From<Option<Outcome>> for GameResult {
+    fn from(outcome: Option<Outcome>) -> Self {
+        match outcome {
+            Some(Outcome::Win(Color::White)) => Self::WhiteWin,
+            Some(Outcome::Win(Color::Black)) => Self::BlackWin,
+            Some(Outcome::Draw(_)) => Self::Draw,
+            None => Self::Unknown,
+        }
+    }
+}
+
+impl Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WhiteWin => "1-0",
+            Self::BlackWin => "0-1",
+            Self::Draw => "1/2-1/2",
+            Self::Unknown => "*",
+        }
+        .fmt(f)
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "1-0" => Self::WhiteWin,
+            "0-1" => Self::BlackWin,
+            "1/2-1/2" => Self::Draw,
+            "*" => Self::Unknown,
+            _ => return Err(()),
+        })
+    }
+}
+
+// Whether a player castles by Chess960 rules, as its king or a rook it may castle with is off the
+// file it starts on in standard chess
+fn castles_as_chess960(player: &Player) -> bool {
+    let rights = player.castling_rights;
+    let has_rights = rights.can_castle_king_side() || rights.can_castle_queen_side();
+
+    (has_rights
+        && player
+            .king
+            .first_one_as_square()
+            .is_some_and(|square| square.file() != Square::E_FILE))
+        || rights
+            .king_side
+            .is_some_and(|square| square.file() != Square::H_FILE)
+        || rights
+            .queen_side
+            .is_some_and(|square| square.file() != Square::A_FILE)
+}
+
+/// A game, along with the tag pairs and result it is stored with in a PGN file.
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub game: Game,
+    pub result: GameResult,
+}
+
+impl PgnGame {
+    /// Wraps a game, giving it the tags of the Seven Tag Roster with unknown values, and taking
+    /// its result from [`Game::outcome`]. Games which don't start from the starting position, on
+    /// the first move with a clean clock, also receive the `SetUp` and `FEN` tags, and those with
+    /// castling rights off the standard files the `Variant` tag.
+    pub fn new(game: Game) -> Self {
+        let result = GameResult::from(game.outcome());
+
+        let mut pgn_game = Self {
+            tags: [
+                ("Event", "?"),
+                ("Site", "?"),
+                ("Date", "????.??.??"),
+                ("Round", "?"),
+                ("White", "?"),
+                ("Black", "?"),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+            game,
+            result,
+        };
+
+        pgn_game.set_tag("Result", &result.to_string());
+
+        let starting_board = *pgn_game.game.starting_board();
+
+        // Hashes leave out the move counters, which the move numbers depend on
+        if starting_board.to_string() != Board::starting_position().to_string() {
+            pgn_game.set_tag("SetUp", "1");
+            pgn_game.set_tag("FEN", &starting_board.to_string());
+        }
+
+        if castles_as_chess960(&starting_board.us) || castles_as_chess960(&starting_board.them) {
+            pgn_game.set_tag("Variant", "Chess960");
+        }
+
+        pgn_game
+    }
+
+    /// Returns the value of the tag with the passed name, if it exists.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets the value of the tag with the passed name, adding the tag if it doesn't exist.
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some((_, tag_value)) => *tag_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+}
+
+impl Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            writeln!(
+                f,
+                "[{name} \"{}\"]",
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            )?;
+        }
+
+        writeln!(f)?;
+
+        let mut board = *self.game.starting_board();
+        let mut tokens = Vec::new();
+
+        for (index, &chess_move) in self.game.moves().iter().enumerate() {
+            if board.playing_color == Color::White {
+                tokens.push(format!("{}.", board.full_moves));
+            } else if index == 0 {
+                tokens.push(format!("{}...", board.full_moves));
+            }
+
+            tokens.push(chess_move.to_san(&board).map_err(|_| fmt::Error)?);
+            board.make_move(chess_move).map_err(|_| fmt::Error)?;
+        }
+
+        tokens.push(self.result.to_string());
+
+        let mut line_length = 0;
+
+        for token in tokens {
+            if line_length != 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+                writeln!(f)?;
+                line_length = 0;
+            } else if line_length != 0 {
+                write!(f, " ")?;
+                line_length += 1;
+            }
+
+            write!(f, "{token}")?;
+            line_length += token.len();
+        }
+
+        writeln!(f)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ParsePgnErrorKind {
+    #[error("failed to read input")]
+    Io(#[source] io::Error),
+    #[error("tag pair is malformed")]
+    MalformedTag,
+    #[error("invalid fen in tag")]
+    InvalidFen(#[source] ParseBoardError),
+    #[error("comment is never closed")]
+    UnterminatedComment,
+    #[error("variation is closed without being opened")]
+    UnbalancedVariation,
+    #[error("unexpected character `{0}`")]
+    UnexpectedChar(char),
+    #[error("game must end with a game termination marker")]
+    MissingResult,
+    #[error("invalid move `{0}`")]
+    InvalidMove(String, #[source] ParseSanError),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid pgn at line {line}, column {column}")]
+pub struct ParsePgnError {
+    pub line: usize,
+    pub column: usize,
+    #[source]
+    pub kind: ParsePgnErrorKind,
+}
+
+/// An iterator over the games in a PGN stream, as returned by [`PgnReader::new`].
+///
+/// When a game fails to parse, the error is returned, and the reader skips to the next line
+/// starting a tag pair, so the games following it can still be read.
+pub struct PgnReader<R: BufRead> {
+    lines: Lines<R>,
+    // The characters of the current line, including its newline.
+    line: Vec<char>,
+    // The 1-based position of the next character to be read.
+    line_number: usize,
+    column: usize,
+    is_finished: bool,
+}
+
+fn is_symbol_char(c: char, allow_dots: bool) -> bool {
+    c.is_ascii_alphanumeric() || "+#=:-/!?_".contains(c) || (allow_dots && c == '.')
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            line: Vec::new(),
+            line_number: 0,
+            column: 1,
+            is_finished: false,
+        }
+    }
+
+    fn error(&self, kind: ParsePgnErrorKind) -> ParsePgnError {
+        self.error_at((self.line_number, self.column), kind)
+    }
+
+    fn error_at(&self, (line, column): (usize, usize), kind: ParsePgnErrorKind) -> ParsePgnError {
+        ParsePgnError { line, column, kind }
+    }
+
+    // Returns `false` once the input is exhausted.
+    fn next_line(&mut self) -> Result<bool, ParsePgnError> {
+        match self.lines.next() {
+            Some(Ok(line)) => {
+                self.line_number += 1;
+                self.column = 1;
+                self.line = line.chars().chain(['\n']).collect();
+
+                Ok(true)
+            }
+            Some(Err(error)) => {
+                self.is_finished = true;
+
+                Err(self.error(ParsePgnErrorKind::Io(error)))
+            }
+            None => {
+                self.is_finished = true;
+
+                Ok(false)
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<char>, ParsePgnError> {
+        while self.column > self.line.len() {
+            if self.is_finished || !self.next_line()? {
+                return Ok(None);
+            }
+
+            // Lines starting with a `%` are escaped, and should be ignored
+            if self.line.first() == Some(&'%') {
+                self.column = self.line.len() + 1;
+            }
+        }
+
+        Ok(Some(self.line[self.column - 1]))
+    }
+
+    fn advance(&mut self) {
+        self.column += 1;
+    }
+
+    fn skip_whitespace(&mut self) -> Result<(), ParsePgnError> {
+        while self.peek()?.is_some_and(char::is_whitespace) {
+            self.advance();
+        }
+
+        Ok(())
+    }
+
+    fn read_while(&mut self, predicate: impl Fn(char) -> bool) -> Result<String, ParsePgnError> {
+        let mut string = String::new();
+
+        while let Some(c) = self.peek()?.filter(|&c| predicate(c)) {
+            string.push(c);
+            self.advance();
+        }
+
+        Ok(string)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParsePgnError> {
+        if self.peek()? == Some(expected) {
+            self.advance();
+
+            Ok(())
+        } else {
+            Err(self.error(ParsePgnErrorKind::MalformedTag))
+        }
+    }
+
+    fn read_tag(&mut self) -> Result<(String, String), ParsePgnError> {
+        self.expect('[')?;
+        self.skip_whitespace()?;
+
+        let name = self.read_while(|c| c.is_ascii_alphanumeric() || c == '_')?;
+
+        if name.is_empty() {
+            return Err(self.error(ParsePgnErrorKind::MalformedTag));
+        }
+
+        self.skip_whitespace()?;
+        self.expect('"')?;
+
+        let mut value = String::new();
+
+        loop {
+            match self.peek()? {
+                Some('"') => break,
+                Some('\\') => {
+                    self.advance();
+
+                    match self.peek()? {
+                        Some(c @ ('"' | '\\')) => value.push(c),
+                        _ => return Err(self.error(ParsePgnErrorKind::MalformedTag)),
+                    }
+                }
+                Some('\n') | None => return Err(self.error(ParsePgnErrorKind::MalformedTag)),
+                Some(c) => value.push(c),
+            }
+
+            self.advance();
+        }
+
+        self.advance();
+        self.skip_whitespace()?;
+        self.expect(']')?;
+
+        Ok((name, value))
+    }
+
+    fn skip_comment(&mut self) -> Result<(), ParsePgnError> {
+        let start = (self.line_number, self.column);
+
+        loop {
+            match self.peek()? {
+                Some('}') => break,
+                Some(_) => self.advance(),
+                None => return Err(self.error_at(start, ParsePgnErrorKind::UnterminatedComment)),
+            }
+        }
+
+        self.advance();
+
+        Ok(())
+    }
+
+    fn read_game(&mut self) -> Result<Option<PgnGame>, ParsePgnError> {
+        self.skip_whitespace()?;
+
+        if self.peek()?.is_none() {
+            return Ok(None);
+        }
+
+        let mut tags = Vec::new();
+
+        while self.peek()? == Some('[') {
+            tags.push(self.read_tag()?);
+            self.skip_whitespace()?;
+        }
+
+        let starting_board = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => Board::from_str(fen)
+                .map_err(|error| self.error(ParsePgnErrorKind::InvalidFen(error)))?,
+            None => Board::starting_position(),
+        };
+
+        let mut game = Game::new(starting_board);
+        let mut variation_depth = 0usize;
+
+        let result = loop {
+            self.skip_whitespace()?;
+
+            let position = (self.line_number, self.column);
+
+            let Some(c) = self.peek()? else {
+                return Err(self.error(ParsePgnErrorKind::MissingResult));
+            };
+
+            match c {
+                '{' => self.skip_comment()?,
+                ';' => {
+                    self.read_while(|c| c != '\n')?;
+                }
+                '(' => {
+                    variation_depth += 1;
+                    self.advance();
+                }
+                ')' => {
+                    variation_depth = variation_depth.checked_sub(1).ok_or_else(|| {
+                        self.error_at(position, ParsePgnErrorKind::UnbalancedVariation)
+                    })?;
+                    self.advance();
+                }
+                '$' => {
+                    self.advance();
+                    self.read_while(|c| c.is_ascii_digit())?;
+                }
+                '*' if variation_depth == 0 => {
+                    self.advance();
+
+                    break GameResult::Unknown;
+                }
+                '*' => self.advance(),
+                '[' => return Err(self.error(ParsePgnErrorKind::MissingResult)),
+                c if is_symbol_char(c, false) => {
+                    // Dots are only part of symbols starting with a letter, such as `e.p.`, and
+                    // otherwise follow move numbers
+                    let allow_dots = c.is_ascii_alphabetic();
+                    let symbol = self.read_while(|c| is_symbol_char(c, allow_dots))?;
+
+                    if let Ok(result) = GameResult::from_str(&symbol) {
+                        if variation_depth == 0 {
+                            break result;
+                        }
+                    } else if symbol.chars().all(|c| c.is_ascii_digit()) {
+                        // Move numbers are followed by one dot for white, and three for black
+                        self.read_while(|c| c == '.')?;
+                    } else if variation_depth == 0
+                        && symbol != "e.p."
+                        && !symbol.chars().all(|c| matches!(c, '!' | '?'))
+                    {
+                        let chess_move =
+                            ChessMove::from_san(&symbol, game.board()).map_err(|error| {
+                                self.error_at(
+                                    position,
+                                    ParsePgnErrorKind::InvalidMove(symbol.clone(), error),
+                                )
+                            })?;
+
+                        // SAFETY: Moves parsed from SAN are always legal
+                        game.make_move(chess_move).unwrap();
+                    }
+                }
+                c => return Err(self.error(ParsePgnErrorKind::UnexpectedChar(c))),
+            }
+        };
+
+        Ok(Some(PgnGame { tags, game, result }))
+    }
+
+    // Skips the rest of a malformed game, up to the next line starting a tag pair. A game missing
+    // its result ends at the first tag pair of the next game, which is left to be read.
+    fn skip_game(&mut self) {
+        if self.column == 1 && self.line.first() == Some(&'[') {
+            return;
+        }
+
+        loop {
+            match self.next_line() {
+                Ok(true) if self.line.first() == Some(&'[') => break,
+                Ok(true) => {}
+                Ok(false) | Err(_) => break,
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, ParsePgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_game() {
+            Ok(Some(pgn_game)) => Some(Ok(pgn_game)),
+            Ok(None) => None,
+            Err(error) => {
+                if !self.is_finished {
+                    self.skip_game();
+                }
+
+                Some(Err(error))
+            }
+        }
+    }
+}
@@ -241,41 +241,39 @@ impl FromStr for ChessMove {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
-pub struct CastlingRights([bool; 64]);
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// The castling rights of a player, stored as the squares of the rooks they may still castle with.
+/// Since the king and rooks may start on any file in Chess960, the rook squares can't be assumed.
+pub struct CastlingRights {
+    pub king_side: Option<Square>,
+    pub queen_side: Option<Square>,
+}
 
 impl CastlingRights {
     pub fn empty() -> Self {
-        Self([false; 64])
+        Self {
+            king_side: None,
+            queen_side: None,
+        }
     }
 
     pub fn can_castle_king_side(&self) -> bool {
-        (self.0[Square::E1] ^ self.0[Square::E8]) && (self.0[Square::H1] ^ self.0[Square::H8])
+        self.king_side.is_some()
     }
 
     pub fn can_castle_queen_side(&self) -> bool {
-        (self.0[Square::E1] ^ self.0[Square::E8]) && (self.0[Square::A1] ^ self.0[Square::A8])
+        self.queen_side.is_some()
     }
 
-    pub fn as_minimized_rights(&self) -> usize {
-        self.0[Square::A1] as usize
-            | ((self.0[Square::H1] as usize) << 1)
-            | ((self.0[Square::A8] as usize) << 2)
-            | ((self.0[Square::H8] as usize) << 3)
-    }
-}
-
-impl Index<Square> for CastlingRights {
-    type Output = bool;
-
-    fn index(&self, index: Square) -> &Self::Output {
-        &self.0[index]
-    }
-}
+    /// Removes the right to castle with the rook on the passed square, if there is such a right.
+    pub fn remove_rook(&mut self, square: Square) {
+        if self.king_side == Some(square) {
+            self.king_side = None;
+        }
 
-impl IndexMut<Square> for CastlingRights {
-    fn index_mut(&mut self, index: Square) -> &mut Self::Output {
-        &mut self.0[index]
+        if self.queen_side == Some(square) {
+            self.queen_side = None;
+        }
     }
 }
 
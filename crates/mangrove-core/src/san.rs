@@ -0,0 +1,263 @@
+//! Support for Standard Algebraic Notation (SAN), the move notation used in PGN files and by most
+//! humans, such as `Nf3`, `exd6`, `O-O-O` or `e8=Q+`. Unlike long algebraic notation, SAN can only
+//! be interpreted relative to a board, as it omits the origin square whenever it isn't needed to
+//! distinguish the move from other legal moves.
+
+use std::str::FromStr;
+
+use mangrove_bootstrap::{ParseSquareError, Square};
+
+use crate::{
+    board::{Board, MakeMoveError},
+    mg,
+    repr::{ChessMove, PieceKind},
+};
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[non_exhaustive]
+pub enum ParseSanError {
+    #[error("move must contain at least a target square")]
+    InvalidLength,
+    #[error("invalid target square")]
+    InvalidTargetSquare(#[source] ParseSquareError),
+    #[error("origin square hint must be a file, a rank, or both")]
+    InvalidDisambiguation,
+    #[error("move is illegal in the used board")]
+    IllegalMove,
+    #[error("move is ambiguous in the used board")]
+    AmbiguousMove,
+}
+
+fn piece_kind_char(kind: PieceKind) -> char {
+    match kind {
+        PieceKind::King => 'K',
+        PieceKind::Queen => 'Q',
+        PieceKind::Rook => 'R',
+        PieceKind::Bishop => 'B',
+        PieceKind::Knight => 'N',
+        PieceKind::Pawn => 'P',
+    }
+}
+
+fn piece_kind_from_char(c: char) -> Option<PieceKind> {
+    Some(match c {
+        'K' => PieceKind::King,
+        'Q' => PieceKind::Queen,
+        'R' => PieceKind::Rook,
+        'B' => PieceKind::Bishop,
+        'N' => PieceKind::Knight,
+        'P' => PieceKind::Pawn,
+        _ => return None,
+    })
+}
+
+fn promotion_from_char(c: char) -> Option<PieceKind> {
+    piece_kind_from_char(c.to_ascii_uppercase()).filter(|kind| PieceKind::PROMOTIONS.contains(kind))
+}
+
+fn is_castle(board: &Board, chess_move: ChessMove) -> bool {
+    board.us.king.get_bit(chess_move.origin) && board.us.rooks.get_bit(chess_move.target)
+}
+
+fn is_capture(board: &Board, chess_move: ChessMove) -> bool {
+    board.them.occupation.get_bit(chess_move.target)
+        || (board.piece_kind_board[chess_move.origin] == Some(PieceKind::Pawn)
+            && chess_move.origin.file() != chess_move.target.file())
+}
+
+impl ChessMove {
+    /// Formats the move in Standard Algebraic Notation, relative to the passed board, in which the
+    /// move must be legal. Check and checkmate suffixes are included, while en passant captures
+    /// are formatted like regular pawn captures, without an `e.p.` suffix.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let board = Board::starting_position();
+    ///
+    /// assert_eq!(ChessMove::from_str("g1f3").unwrap().to_san(&board).unwrap(), "Nf3");
+    /// ```
+    pub fn to_san(&self, board: &Board) -> Result<String, MakeMoveError> {
+        let moves = mg::gen_moves(board);
+
+        if !moves.contains(self) {
+            return Err(MakeMoveError);
+        }
+
+        // SAFETY: Legal moves always have a piece on their origin square
+        let kind = board.piece_kind_board[self.origin].unwrap();
+        let mut san = String::new();
+
+        if is_castle(board, *self) {
+            san.push_str(if self.target.file() > self.origin.file() {
+                "O-O"
+            } else {
+                "O-O-O"
+            });
+        } else {
+            if kind == PieceKind::Pawn {
+                if is_capture(board, *self) {
+                    san.push((b'a' + self.origin.file()) as char);
+                }
+            } else {
+                san.push(piece_kind_char(kind));
+
+                // Other pieces of the same kind which can also reach the target square
+                let rivals = moves
+                    .iter()
+                    .filter(|rival| {
+                        rival.target == self.target
+                            && rival.origin != self.origin
+                            && board.piece_kind_board[rival.origin] == Some(kind)
+                    })
+                    .map(|rival| rival.origin)
+                    .collect::<Vec<_>>();
+
+                if !rivals.is_empty() {
+                    if rivals
+                        .iter()
+                        .all(|rival| rival.file() != self.origin.file())
+                    {
+                        san.push((b'a' + self.origin.file()) as char);
+                    } else if rivals
+                        .iter()
+                        .all(|rival| rival.rank() != self.origin.rank())
+                    {
+                        san.push((b'1' + self.origin.rank()) as char);
+                    } else {
+                        san.push_str(&self.origin.to_string());
+                    }
+                }
+            }
+
+            if is_capture(board, *self) {
+                san.push('x');
+            }
+
+            san.push_str(&self.target.to_string());
+
+            if let Some(promotion) = self.promotion {
+                san.push('=');
+                san.push(piece_kind_char(promotion));
+            }
+        }
+
+        let mut next_board = *board;
+        next_board.make_move(*self)?;
+
+        if next_board.in_check() {
+            san.push(if mg::gen_moves(&next_board).is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+
+        Ok(san)
+    }
+
+    /// Parses a move written in Standard Algebraic Notation, relative to the passed board. The
+    /// parser is lenient, and accepts a few common deviations from the standard, such as `0-0`
+    /// for castling, `e.p.` suffixes, missing or superfluous check marks, annotations like `!?`,
+    /// promotions without an `=`, hyphenated or fully specified origin squares (`Ng1-f3`) and
+    /// plain long algebraic notation (`e2e4`).
+    ///
+    /// The move is resolved against the legal moves of the board, and so an error is returned if
+    /// no legal move, or more than one legal move, matches it.
+    pub fn from_san(san: &str, board: &Board) -> Result<Self, ParseSanError> {
+        let moves = mg::gen_moves(board);
+
+        let san = san.trim();
+        let san = san.strip_suffix("e.p.").unwrap_or(san).trim_end();
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+
+        let find_unique = |mut candidates: Vec<ChessMove>| match candidates.len() {
+            0 => Err(ParseSanError::IllegalMove),
+            1 => Ok(candidates.pop().unwrap()),
+            _ => Err(ParseSanError::AmbiguousMove),
+        };
+
+        let castle_side = match san {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+
+        if let Some(is_king_side) = castle_side {
+            return find_unique(
+                moves
+                    .into_iter()
+                    .filter(|&chess_move| {
+                        is_castle(board, chess_move)
+                            && (chess_move.target.file() > chess_move.origin.file()) == is_king_side
+                    })
+                    .collect(),
+            );
+        }
+
+        let mut characters = san
+            .chars()
+            .filter(|character| !matches!(character, 'x' | ':' | '-' | '=' | '(' | ')'))
+            .collect::<Vec<_>>();
+
+        let kind = characters.first().copied().and_then(piece_kind_from_char);
+
+        if kind.is_some() {
+            characters.remove(0);
+        }
+
+        let promotion = match characters.as_slice() {
+            [.., rank, promotion] if rank.is_ascii_digit() => {
+                let promotion = promotion_from_char(*promotion);
+
+                if promotion.is_some() {
+                    characters.pop();
+                }
+
+                promotion
+            }
+            _ => None,
+        };
+
+        if characters.len() < 2 {
+            return Err(ParseSanError::InvalidLength);
+        }
+
+        let target = Square::from_str(
+            &characters
+                .split_off(characters.len() - 2)
+                .into_iter()
+                .collect::<String>(),
+        )
+        .map_err(ParseSanError::InvalidTargetSquare)?;
+
+        let (origin_file, origin_rank) = match characters.as_slice() {
+            [] => (None, None),
+            [file @ 'a'..='h'] => (Some(*file as u8 - b'a'), None),
+            [rank @ '1'..='8'] => (None, Some(*rank as u8 - b'1')),
+            [file @ 'a'..='h', rank @ '1'..='8'] => {
+                (Some(*file as u8 - b'a'), Some(*rank as u8 - b'1'))
+            }
+            _ => return Err(ParseSanError::InvalidDisambiguation),
+        };
+
+        // Long algebraic notation doesn't specify the piece kind, which isn't needed when the
+        // origin square is fully known.
+        let kind =
+            kind.or((origin_file.is_none() || origin_rank.is_none()).then_some(PieceKind::Pawn));
+
+        find_unique(
+            moves
+                .into_iter()
+                .filter(|chess_move| {
+                    chess_move.target == target
+                        && chess_move.promotion == promotion
+                        && origin_file.is_none_or(|file| chess_move.origin.file() == file)
+                        && origin_rank.is_none_or(|rank| chess_move.origin.rank() == rank)
+                        && kind.is_none_or(|kind| {
+                            board.piece_kind_board[chess_move.origin] == Some(kind)
+                        })
+                })
+                .collect(),
+        )
+    }
+}
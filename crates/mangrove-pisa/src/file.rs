@@ -0,0 +1,146 @@
+//! Saving and loading of networks. Network files hold the weights of a network along with the
+//! config it was initialized with, so networks can be loaded without knowing their shape.
+
+use std::path::PathBuf;
+
+use burn::{
+    config::{Config, ConfigError},
+    module::{Module, ModuleVisitor, ParamId},
+    record::{FullPrecisionSettings, NamedMpkFileRecorder, Record, Recorder, RecorderError},
+    tensor::{backend::Backend, Tensor},
+};
+
+use crate::{Pisa, PisaConfig, PisaRecord};
+
+/// The version of network files written by [`Pisa::save`]. It changes whenever the layout of the
+/// file or the architecture of the network changes, and only files of this version can be loaded.
+pub const PISA_FILE_VERSION: u32 = 2;
+
+#[derive(Record)]
+pub(crate) struct PisaFileRecord<B: Backend> {
+    pub(crate) version: u32,
+    // The config as JSON, as configs aren't records
+    pub(crate) config: String,
+    pub(crate) network: PisaRecord<B>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum PisaFileError {
+    #[error("network file could not be read or written")]
+    Recorder(#[source] RecorderError),
+    #[error("network file has version {found}, but only version {supported} is supported")]
+    UnsupportedVersion { found: u32, supported: u32 },
+    #[error("network file holds an invalid config")]
+    InvalidConfig(#[source] ConfigError),
+    #[error("network has {found} parameter tensors, but its config expects {expected}")]
+    ParameterCountMismatch { found: usize, expected: usize },
+    #[error("network has a parameter of shape {found:?}, but its config expects {expected:?}")]
+    ParameterShapeMismatch {
+        found: Vec<usize>,
+        expected: Vec<usize>,
+    },
+    #[error("network has a move history of {found}, but its config expects {expected}")]
+    MoveHistoryMismatch { found: usize, expected: usize },
+}
+
+pub(crate) fn recorder() -> NamedMpkFileRecorder<FullPrecisionSettings> {
+    NamedMpkFileRecorder::new()
+}
+
+// Gathers the shapes of the parameters of a network, in the order they are visited
+#[derive(Default)]
+struct ParameterShapes(Vec<Vec<usize>>);
+
+impl<B: Backend> ModuleVisitor<B> for ParameterShapes {
+    fn visit<const D: usize>(&mut self, _id: &ParamId, tensor: &Tensor<B, D>) {
+        self.0.push(tensor.dims().to_vec());
+    }
+}
+
+fn parameter_shapes<B: Backend>(network: &Pisa<B>) -> Vec<Vec<usize>> {
+    let mut parameter_shapes = ParameterShapes::default();
+    network.visit(&mut parameter_shapes);
+
+    parameter_shapes.0
+}
+
+impl PisaConfig {
+    // Checks that the parameters of a network have the shapes of those this config initializes
+    // networks with, so mismatches are reported before the network is run with tensors of the
+    // wrong shapes. Records loaded into a network replace its tensors without checking them.
+    fn check_parameters<B: Backend>(&self, network: &Pisa<B>) -> Result<(), PisaFileError> {
+        let expected_shapes = parameter_shapes(&self.init::<B>());
+        let found_shapes = parameter_shapes(network);
+
+        if found_shapes.len() != expected_shapes.len() {
+            return Err(PisaFileError::ParameterCountMismatch {
+                found: found_shapes.len(),
+                expected: expected_shapes.len(),
+            });
+        }
+
+        match found_shapes
+            .into_iter()
+            .zip(expected_shapes)
+            .find(|(found, expected)| found != expected)
+        {
+            Some((found, expected)) => {
+                Err(PisaFileError::ParameterShapeMismatch { found, expected })
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<B: Backend> Pisa<B> {
+    /// Saves the network along with the config it was initialized with, which must match it. The
+    /// file is written to the passed path, with an `mpk` extension.
+    pub fn save(&self, config: &PisaConfig, path: impl Into<PathBuf>) -> Result<(), PisaFileError> {
+        // The move history isn't a parameter, so it must be checked on its own
+        if self.move_history() != config.move_history {
+            return Err(PisaFileError::MoveHistoryMismatch {
+                found: self.move_history(),
+                expected: config.move_history,
+            });
+        }
+
+        config.check_parameters(self)?;
+
+        recorder()
+            .record(
+                PisaFileRecord {
+                    version: PISA_FILE_VERSION,
+                    config: config.to_string(),
+                    network: self.clone().into_record(),
+                },
+                path.into(),
+            )
+            .map_err(PisaFileError::Recorder)?;
+
+        Ok(())
+    }
+
+    /// Loads a network saved with [`Pisa::save`], along with its config. The `mpk` extension is
+    /// added to the passed path.
+    pub fn load(path: impl Into<PathBuf>) -> Result<(Self, PisaConfig), PisaFileError> {
+        let record: PisaFileRecord<B> = recorder()
+            .load(path.into())
+            .map_err(PisaFileError::Recorder)?;
+
+        if record.version != PISA_FILE_VERSION {
+            return Err(PisaFileError::UnsupportedVersion {
+                found: record.version,
+                supported: PISA_FILE_VERSION,
+            });
+        }
+
+        let config = PisaConfig::load_binary(record.config.as_bytes())
+            .map_err(PisaFileError::InvalidConfig)?;
+        let network = config.init::<B>().load_record(record.network);
+
+        config.check_parameters(&network)?;
+
+        Ok((network, config))
+    }
+}
@@ -1,9 +1,15 @@
 use burn::tensor::{backend::Backend, Shape, Tensor};
 use mangrove_bootstrap::{BitBoard, Color, Square};
-use mangrove_core::{board::Board, repr::Player};
+use mangrove_core::{
+    board::Board,
+    game::{self, FIFTY_MOVE_RULE_PLIES},
+    repr::Player,
+};
 
+mod file;
 mod model;
 
+pub use file::*;
 pub use model::*;
 
 fn bitboard_to_tensor<B: Backend>(bitboard: BitBoard) -> Tensor<B, 2> {
@@ -33,18 +39,38 @@ fn boolean_to_tensor<B: Backend>(boolean: bool) -> Tensor<B, 2> {
     }
 }
 
-fn board_to_tensor<B: Backend>(board: &Board) -> Tensor<B, 3> {
-    Tensor::cat(
-        vec![player_to_tensor(&board.us), player_to_tensor(&board.them)],
+fn square_to_tensor<B: Backend>(square: Option<Square>) -> Tensor<B, 2> {
+    Tensor::from_floats(Square::ALL.map(|other_square| f32::from(square == Some(other_square))))
+        .reshape(Shape::new([8, 8]))
+}
+
+// Marks whether a board was reached at least once or twice before, given the number of times
+fn repetitions_to_tensor<B: Backend>(repetitions: usize) -> Tensor<B, 3> {
+    Tensor::stack(
+        vec![
+            boolean_to_tensor(repetitions >= 1),
+            boolean_to_tensor(repetitions >= 2),
+        ],
         0,
     )
 }
 
-fn final_board_to_tensor<B: Backend>(board: &Board) -> Tensor<B, 3> {
+fn board_to_tensor<B: Backend>(board: &Board, repetitions: usize) -> Tensor<B, 3> {
     Tensor::cat(
         vec![
             player_to_tensor(&board.us),
             player_to_tensor(&board.them),
+            repetitions_to_tensor(repetitions),
+        ],
+        0,
+    )
+}
+
+fn final_board_to_tensor<B: Backend>(board: &Board, repetitions: usize) -> Tensor<B, 3> {
+    Tensor::cat(
+        vec![
+            board_to_tensor(board, repetitions),
+            square_to_tensor(board.en_passant_capture_square).unsqueeze(),
             boolean_to_tensor(board.us.castling_rights.can_castle_king_side()).unsqueeze(),
             boolean_to_tensor(board.us.castling_rights.can_castle_queen_side()).unsqueeze(),
             boolean_to_tensor(board.them.castling_rights.can_castle_king_side()).unsqueeze(),
@@ -54,29 +80,247 @@ fn final_board_to_tensor<B: Backend>(board: &Board) -> Tensor<B, 3> {
                 Color::Black => Tensor::ones(Shape::new([8, 8])).neg(),
             }
             .unsqueeze(),
+            Tensor::ones(Shape::new([1, 8, 8]))
+                .mul_scalar((board.min_ply_clock as f32 / FIFTY_MOVE_RULE_PLIES as f32).min(1.0)),
         ],
         0,
     )
 }
 
-pub fn boards_to_tensor<B: Backend>(boards: &[Board], move_history: usize) -> Tensor<B, 3> {
-    let final_board_tensor = final_board_to_tensor(boards.last().unwrap());
+/// A board of a game, along with the number of times it was reached earlier in the game. The
+/// repetitions are counted over the whole game, as the encoded boards may not reach back to its
+/// last capture or pawn move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryBoard {
+    pub board: Board,
+    pub repetitions: usize,
+}
 
-    let mut board_tensors = boards[..boards.len()]
+impl HistoryBoard {
+    /// Pairs a board with the number of times it was reached in a game, given the hashes of every
+    /// position reached in the game up to and including the board.
+    pub fn new(board: Board, hashes: &[u64]) -> Self {
+        Self {
+            board,
+            repetitions: game::repetitions(&board, hashes) - 1,
+        }
+    }
+}
+
+impl From<Board> for HistoryBoard {
+    /// Pairs a board with no repetitions, as when it was never reached earlier in the game.
+    fn from(board: Board) -> Self {
+        Self {
+            board,
+            repetitions: 0,
+        }
+    }
+}
+
+/// Encodes the last boards of a game, oldest first, as the input of a network with the passed move
+/// history. Only the last `move_history` boards are encoded, and missing older boards are encoded as
+/// zeros.
+pub fn boards_to_tensor<B: Backend>(boards: &[HistoryBoard], move_history: usize) -> Tensor<B, 3> {
+    let boards = &boards[boards.len().saturating_sub(move_history)..];
+    let (final_board, historical_boards) = boards.split_last().unwrap();
+
+    let mut board_tensors = historical_boards
         .iter()
-        .map(board_to_tensor)
+        .map(|board| board_to_tensor(&board.board, board.repetitions))
         .collect::<Vec<_>>();
 
-    board_tensors.push(final_board_tensor);
-    board_tensors.insert(
-        0,
-        Tensor::zeros(Shape::new([
-            model::calculate_board_tensor_dimension(move_history)
-                - model::calculate_board_tensor_dimension(boards.len()),
-            8,
-            8,
-        ])),
-    );
+    board_tensors.push(final_board_to_tensor(
+        &final_board.board,
+        final_board.repetitions,
+    ));
+
+    let missing_dimension = model::calculate_board_tensor_dimension(move_history)
+        - model::calculate_board_tensor_dimension(boards.len());
+
+    if missing_dimension > 0 {
+        board_tensors.insert(0, Tensor::zeros(Shape::new([missing_dimension, 8, 8])));
+    }
 
     Tensor::cat(board_tensors, 0)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, str::FromStr};
+
+    use burn::{module::Module, record::Recorder};
+    use burn_ndarray::NdArray;
+    use mangrove_core::{board::Board, repr::ChessMove};
+
+    use crate::{
+        boards_to_tensor,
+        file::{recorder, PisaFileRecord},
+        model::calculate_board_tensor_dimension,
+        HistoryBoard, Pisa, PisaConfig, PisaFileError, FINAL_BOARD_DIMENSION, PISA_FILE_VERSION,
+        SINGLE_BOARD_DIMENSION,
+    };
+
+    // The boards of a game where the knights move out and back twice, repeating the starting
+    // position
+    fn repeating_game() -> Vec<HistoryBoard> {
+        let mut board = Board::starting_position();
+        let mut hashes = vec![board.hash];
+        let mut boards = vec![HistoryBoard::new(board, &hashes)];
+
+        for chess_move in ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(2) {
+            board
+                .make_move(ChessMove::from_str(chess_move).unwrap())
+                .unwrap();
+            hashes.push(board.hash);
+            boards.push(HistoryBoard::new(board, &hashes));
+        }
+
+        boards
+    }
+
+    #[test]
+    fn input_shape_test() {
+        let boards = repeating_game();
+
+        for move_history in 1..boards.len() {
+            for board_count in 1..=boards.len() {
+                let input = boards_to_tensor::<NdArray>(&boards[..board_count], move_history);
+
+                assert_eq!(
+                    input.dims(),
+                    [calculate_board_tensor_dimension(move_history), 8, 8],
+                    "{board_count} boards with a move history of {move_history}"
+                );
+            }
+        }
+    }
+
+    // Returns the value of every square of a layer of the final board of the input
+    fn final_board_layer(input: &[f32], move_history: usize, layer: usize) -> &[f32] {
+        let start = (SINGLE_BOARD_DIMENSION * (move_history - 1) + layer) * 64;
+
+        &input[start..start + 64]
+    }
+
+    #[test]
+    fn input_repetitions_test() {
+        let boards = repeating_game();
+        // The first occurrence of the starting position is left out of the encoded boards, but
+        // still counts as a repetition
+        let move_history = 8;
+        let input = boards_to_tensor::<NdArray>(&boards, move_history)
+            .into_data()
+            .convert::<f32>()
+            .value;
+
+        // The starting position is reached for the third time
+        assert!(final_board_layer(&input, move_history, 12)
+            .iter()
+            .all(|&value| value == 1.0));
+        assert!(final_board_layer(&input, move_history, 13)
+            .iter()
+            .all(|&value| value == 1.0));
+
+        // Eight plies were made since the last capture or pawn move in the final board
+        assert!(
+            final_board_layer(&input, move_history, FINAL_BOARD_DIMENSION - 1)
+                .iter()
+                .all(|&value| value == 0.08)
+        );
+    }
+
+    #[test]
+    fn input_en_passant_test() {
+        let board =
+            Board::from_str("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3")
+                .unwrap();
+        let input = boards_to_tensor::<NdArray>(&[board.into()], 1)
+            .into_data()
+            .convert::<f32>()
+            .value;
+        let en_passant_layer = final_board_layer(&input, 1, SINGLE_BOARD_DIMENSION);
+
+        assert_eq!(en_passant_layer.iter().sum::<f32>(), 1.0);
+        assert_eq!(
+            en_passant_layer[board.en_passant_capture_square.unwrap().as_index()],
+            1.0
+        );
+    }
+
+    #[test]
+    fn save_load_test() {
+        let config = PisaConfig::small();
+        let network = config.init::<NdArray>();
+        let path = env::temp_dir().join(format!("pisa-save-load-test-{}", std::process::id()));
+
+        network.save(&config, &path).unwrap();
+        let (loaded_network, loaded_config) = Pisa::<NdArray>::load(&path).unwrap();
+
+        assert_eq!(loaded_config.to_string(), config.to_string());
+        assert_eq!(loaded_network.num_params(), network.num_params());
+
+        // The loaded network evaluates boards exactly like the saved one
+        let boards = [Board::starting_position().into()];
+        let results = network.process(vec![&boards]);
+        let loaded_results = loaded_network.process(vec![&boards]);
+
+        assert_eq!(loaded_results[0].value, results[0].value);
+
+        let _ = std::fs::remove_file(path.with_extension("mpk"));
+    }
+
+    #[test]
+    fn save_mismatched_config_test() {
+        let network = PisaConfig::small().init::<NdArray>();
+        let path = env::temp_dir().join(format!("pisa-mismatch-test-{}", std::process::id()));
+
+        assert!(matches!(
+            network.save(&PisaConfig::small().with_se_blocks(3), &path),
+            Err(PisaFileError::ParameterCountMismatch { .. })
+        ));
+        assert!(matches!(
+            network.save(&PisaConfig::small().with_filters(64), &path),
+            Err(PisaFileError::ParameterShapeMismatch { .. })
+        ));
+        assert!(matches!(
+            network.save(&PisaConfig::small().with_move_history(4), &path),
+            Err(PisaFileError::MoveHistoryMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn load_mismatched_config_test() {
+        // Saving rules out files whose config doesn't match their network, so one is written
+        // directly
+        let path = env::temp_dir().join(format!("pisa-load-mismatch-test-{}", std::process::id()));
+
+        recorder()
+            .record(
+                PisaFileRecord {
+                    version: PISA_FILE_VERSION,
+                    config: PisaConfig::small().to_string(),
+                    network: PisaConfig::small()
+                        .with_filters(64)
+                        .init::<NdArray>()
+                        .into_record(),
+                },
+                path.clone(),
+            )
+            .unwrap();
+
+        assert!(matches!(
+            Pisa::<NdArray>::load(&path),
+            Err(PisaFileError::ParameterShapeMismatch { .. })
+        ));
+
+        let _ = std::fs::remove_file(path.with_extension("mpk"));
+    }
+
+    #[test]
+    fn load_missing_file_test() {
+        assert!(matches!(
+            Pisa::<NdArray>::load(env::temp_dir().join("pisa-missing-file-test")),
+            Err(PisaFileError::Recorder(_))
+        ));
+    }
+}
@@ -9,28 +9,31 @@ use burn::{
     tensor::{activation, backend::Backend, Shape, Tensor},
 };
 use mangrove_bootstrap::Square;
-use mangrove_core::{
-    board::Board,
-    repr::{ChessMove, PieceKind},
-};
+use mangrove_core::repr::{ChessMove, PieceKind};
 use std::{
     iter,
     ops::{Index, IndexMut},
 };
 
-use crate::boards_to_tensor;
+use crate::{boards_to_tensor, HistoryBoard};
 
 // TODO: Consider placing some of the information here in the input instead of in each historical board state.
-// The 3rd dimension value of the shape of a board tensor.
+// The 3rd dimension value of the shape of a historical board tensor.
+#[rustfmt::skip]
+pub const SINGLE_BOARD_DIMENSION: usize =
+    6 // 6 piece kinds for the player to move
+        + 6 // 6 piece kinds for the other player
+        + 2; // 2 layers for whether the board was reached at least once or twice before
+
+// The 3rd dimension value of the shape of the final board tensor.
 #[rustfmt::skip]
 pub const FINAL_BOARD_DIMENSION: usize =
-    6 // 6 piece kinds for white 
-        + 6 // 6 piece kinds for black 
+    SINGLE_BOARD_DIMENSION
         + 1 // 1 layer for the en passant square
-        + 2 // 2 ways to castle (king-side, queen-side) for white
-        + 2 // 2 ways to castle (king-side, queen-side) for black
-        + 1; // 1 layer to denote who is playing. 1 = white, -1 = black.
-pub const SINGLE_BOARD_DIMENSION: usize = 6 + 6;
+        + 2 // 2 ways to castle (king-side, queen-side) for the player to move
+        + 2 // 2 ways to castle (king-side, queen-side) for the other player
+        + 1 // 1 layer to denote who is playing. 1 = white, -1 = black.
+        + 1; // 1 layer for the plies since the last capture or pawn move, as a share of 100
 
 // The output size is simply the length of the vector output by the model. It encodes all Chess
 // moves and a position value node. Note that it does overshoot the number of possible Chess moves
@@ -260,7 +263,7 @@ impl<B: Backend> Pisa<B> {
         }
     }
 
-    pub fn process(&self, input: Vec<&[Board]>) -> Vec<PisaResult> {
+    pub fn process(&self, input: Vec<&[HistoryBoard]>) -> Vec<PisaResult> {
         let batch_output = self.forward(Tensor::stack(
             input
                 .iter()
@@ -296,24 +299,33 @@ impl<B: Backend> Pisa<B> {
 #[derive(Config, Debug)]
 pub struct PisaConfig {
     #[config(default = 1)]
-    initial_kernel_stride: usize,
+    pub initial_kernel_stride: usize,
     #[config(default = 3)]
-    initial_kernel_length: usize,
+    pub initial_kernel_length: usize,
     #[config(default = 1000)]
-    hidden_layer_size: usize,
+    pub hidden_layer_size: usize,
     #[config(default = 10)]
-    se_blocks: usize,
+    pub se_blocks: usize,
     #[config(default = 8)]
-    move_history: usize,
+    pub move_history: usize,
     #[config(default = 3)]
-    kernel_length: usize,
+    pub kernel_length: usize,
     #[config(default = 128)]
-    filters: usize,
+    pub filters: usize,
     #[config(default = 16)]
-    ratio: usize,
+    pub ratio: usize,
 }
 
 impl PisaConfig {
+    /// Returns the config of a small network, which runs quickly on the CPU, for tests and
+    /// benchmarks which don't depend on the strength of the network.
+    pub fn small() -> Self {
+        Self::new()
+            .with_se_blocks(2)
+            .with_filters(32)
+            .with_hidden_layer_size(128)
+    }
+
     pub fn init<B: Backend>(&self) -> Pisa<B> {
         Pisa {
             move_history: self.move_history,
@@ -0,0 +1,47 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mangrove_core::board::Board;
+use mangrove_search::{
+    evaluation::{LeafEvaluator, MaterialEvaluator, UniformEvaluator},
+    tree::Tree,
+};
+
+use boxcar as _;
+use burn as _;
+use burn_ndarray as _;
+use mangrove_pisa as _;
+use rand as _;
+use rand_distr as _;
+use ringbuffer as _;
+use test_case as _;
+use thiserror as _;
+use tracing as _;
+
+const PLAYOUTS: usize = 1024;
+
+fn grow(evaluator: &impl LeafEvaluator) -> Tree {
+    let tree = Tree::new(Board::starting_position());
+
+    for _ in 0..PLAYOUTS {
+        tree.grow(evaluator);
+    }
+
+    tree
+}
+
+// Evaluators without a network make the cost of the search itself visible
+fn evaluators_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluators");
+    group.throughput(Throughput::Elements(PLAYOUTS as u64));
+
+    group.bench_function(BenchmarkId::new("playouts", "uniform"), |b| {
+        b.iter(|| grow(&UniformEvaluator))
+    });
+    group.bench_function(BenchmarkId::new("playouts", "material"), |b| {
+        b.iter(|| grow(&MaterialEvaluator))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, evaluators_benchmark);
+criterion_main!(benches);
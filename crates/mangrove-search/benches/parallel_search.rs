@@ -0,0 +1,72 @@
+use std::{thread, time::Duration};
+
+use burn_ndarray::NdArray;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use mangrove_core::board::Board;
+use mangrove_pisa::PisaConfig;
+use mangrove_search::{
+    evaluation::{BatchParameters, EvaluationQueue},
+    tree::Tree,
+};
+
+use boxcar as _;
+use burn as _;
+use rand as _;
+use rand_distr as _;
+use ringbuffer as _;
+use test_case as _;
+use thiserror as _;
+use tracing as _;
+
+const THREADS: [usize; 4] = [1, 2, 4, 8];
+
+// Divisible by every thread count, so each thread runs the same number of playouts
+const PLAYOUTS: usize = 256;
+
+fn parallel_search_benchmark(c: &mut Criterion) {
+    let network = PisaConfig::small().init::<NdArray>();
+
+    let mut group = c.benchmark_group("parallel search");
+    group.throughput(Throughput::Elements(PLAYOUTS as u64));
+    group.sample_size(10);
+
+    for threads in THREADS {
+        group.bench_with_input(
+            BenchmarkId::new("playouts", threads),
+            &threads,
+            |b, &threads| {
+                // Every thread waits on its leaf, so batches can hold at most one leaf per thread
+                let evaluation_queue = EvaluationQueue::start(
+                    network.clone(),
+                    BatchParameters {
+                        batch_size: threads,
+                        timeout: Duration::from_millis(1),
+                    },
+                );
+
+                b.iter_batched(
+                    || Tree::new(Board::starting_position()),
+                    |tree| {
+                        thread::scope(|scope| {
+                            for _ in 0..threads {
+                                scope.spawn(|| {
+                                    for _ in 0..PLAYOUTS / threads {
+                                        tree.grow(&evaluation_queue);
+                                    }
+                                });
+                            }
+                        });
+
+                        tree
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, parallel_search_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use burn_ndarray::NdArray;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mangrove_core::board::Board;
+use mangrove_pisa::PisaConfig;
+use mangrove_search::{
+    evaluation::{BatchParameters, EvaluationQueue},
+    tree::Tree,
+};
+
+use boxcar as _;
+use burn as _;
+use rand as _;
+use rand_distr as _;
+use ringbuffer as _;
+use test_case as _;
+use thiserror as _;
+use tracing as _;
+
+const PLAYOUTS: usize = 256;
+
+fn grow(evaluation_queue: &EvaluationQueue, transpositions: bool) -> Tree {
+    let tree = Tree::new(Board::starting_position()).with_transpositions(transpositions);
+
+    for _ in 0..PLAYOUTS {
+        tree.grow(evaluation_queue);
+    }
+
+    tree
+}
+
+fn transpositions_benchmark(c: &mut Criterion) {
+    let network = PisaConfig::small().init::<NdArray>();
+
+    let evaluation_queue = EvaluationQueue::start(
+        network,
+        BatchParameters {
+            batch_size: 1,
+            timeout: Duration::ZERO,
+        },
+    );
+
+    let mut group = c.benchmark_group("transpositions");
+    group.throughput(Throughput::Elements(PLAYOUTS as u64));
+    group.sample_size(10);
+
+    for (name, transpositions) in [("separate", false), ("shared", true)] {
+        // Evaluations saved by sharing transpositions show up as faster playouts, but the number of
+        // evaluations is also worth seeing directly
+        println!(
+            "{name} transpositions: {} evaluations in {PLAYOUTS} playouts",
+            grow(&evaluation_queue, transpositions).evaluations()
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("playouts", name),
+            &transpositions,
+            |b, &transpositions| b.iter(|| grow(&evaluation_queue, transpositions)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, transpositions_benchmark);
+criterion_main!(benches);
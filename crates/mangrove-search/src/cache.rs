@@ -0,0 +1,261 @@
+//! Caching of evaluations, so positions reached again across moves, transpositions and games are
+//! evaluated once.
+
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use mangrove_core::{mg, repr::ChessMove};
+use mangrove_pisa::{HistoryBoard, MoveProbabilities, PisaResult};
+
+use crate::evaluation::Evaluator;
+
+/// Returns the key of a history of boards in the cache. The key covers every board of the history,
+/// in order, with its repetitions, and the fifty-move clock of the last board, as the network sees
+/// all of them, while hashes leave the repetitions and the clock out.
+pub(crate) fn history_key(boards: &[HistoryBoard]) -> u64 {
+    let min_ply_clock = boards.last().map_or(0, |board| board.board.min_ply_clock);
+
+    // Multiplying between values makes the key depend on their order
+    boards
+        .iter()
+        .flat_map(|board| [board.board.hash, board.repetitions as u64])
+        .chain([min_ply_clock as u64])
+        .fold(boards.len() as u64, |key, value| {
+            (key ^ value).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        })
+}
+
+struct CacheEntry {
+    key: u64,
+    // Whether the entry was used since the clock hand last passed it
+    referenced: bool,
+    value: f32,
+    // Only the probabilities of the legal moves of the board are kept, as no others are read
+    move_probabilities: Box<[(f32, ChessMove)]>,
+}
+
+impl CacheEntry {
+    fn memory_usage(&self) -> usize {
+        mem::size_of::<CacheEntry>()
+            + mem::size_of::<(u64, usize)>()
+            + mem::size_of_val(&*self.move_probabilities)
+    }
+}
+
+#[derive(Default)]
+struct Clock {
+    entries: Vec<CacheEntry>,
+    // The indices of the entries, keyed by their keys
+    indices: HashMap<u64, usize>,
+    hand: usize,
+    memory_usage: usize,
+}
+
+impl Clock {
+    // Evicts the first entry the hand reaches which wasn't used since the hand last passed it,
+    // giving every used entry it passes a second chance
+    fn evict(&mut self) {
+        loop {
+            let entry = &mut self.entries[self.hand];
+
+            if entry.referenced {
+                entry.referenced = false;
+                self.hand = (self.hand + 1) % self.entries.len();
+                continue;
+            }
+
+            let entry = self.entries.swap_remove(self.hand);
+            self.indices.remove(&entry.key);
+            self.memory_usage -= entry.memory_usage();
+
+            // The last entry took the place of the evicted one
+            if let Some(moved_entry) = self.entries.get(self.hand) {
+                self.indices.insert(moved_entry.key, self.hand);
+            } else {
+                self.hand = 0;
+            }
+
+            return;
+        }
+    }
+}
+
+/// How often the evaluations looked up in a cache were found.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStatistics {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStatistics {
+    /// The share of lookups which were found, or `None` if nothing was looked up yet.
+    pub fn hit_rate(&self) -> Option<f32> {
+        let lookups = self.hits + self.misses;
+
+        (lookups > 0).then(|| self.hits as f32 / lookups as f32)
+    }
+}
+
+/// A cache of evaluations, keyed by the histories of boards they were evaluated from, using
+/// roughly a fixed amount of memory. Once full, entries are replaced with the clock algorithm,
+/// which approximates replacing the least recently used entry. The cache is guarded by a mutex,
+/// and may be shared by any number of threads.
+pub struct EvaluationCache {
+    clock: Mutex<Clock>,
+    memory_limit: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl EvaluationCache {
+    /// Creates an empty cache using roughly the passed amount of memory, in bytes.
+    pub fn new(memory_limit: usize) -> Self {
+        Self {
+            clock: Mutex::default(),
+            memory_limit,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the number of evaluations in the cache.
+    pub fn len(&self) -> usize {
+        self.clock.lock().expect("mutex is poisoned").entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns roughly how much memory the evaluations in the cache use, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.clock.lock().expect("mutex is poisoned").memory_usage
+    }
+
+    pub fn statistics(&self) -> CacheStatistics {
+        CacheStatistics {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Looks up the evaluation of the last board of a history.
+    pub fn get(&self, boards: &[HistoryBoard]) -> Option<PisaResult> {
+        let key = history_key(boards);
+        let mut clock = self.clock.lock().expect("mutex is poisoned");
+
+        let Some(&index) = clock.indices.get(&key) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+
+        self.hits.fetch_add(1, Ordering::Relaxed);
+
+        let entry = &mut clock.entries[index];
+        entry.referenced = true;
+
+        Some(PisaResult {
+            value: entry.value,
+            move_probabilities: MoveProbabilities::new(entry.move_probabilities.iter().copied()),
+        })
+    }
+
+    /// Stores the evaluation of the last board of a history, evicting other evaluations until it
+    /// fits.
+    pub fn insert(&self, boards: &[HistoryBoard], result: &PisaResult) {
+        let entry = CacheEntry {
+            key: history_key(boards),
+            referenced: false,
+            value: result.value,
+            move_probabilities: mg::gen_moves(&boards.last().unwrap().board)
+                .into_iter()
+                .map(|chess_move| (result.move_probabilities[chess_move], chess_move))
+                .collect(),
+        };
+        let entry_memory_usage = entry.memory_usage();
+
+        if entry_memory_usage > self.memory_limit {
+            return;
+        }
+
+        let mut clock = self.clock.lock().expect("mutex is poisoned");
+
+        // Another thread may have evaluated the same history in the meantime
+        if clock.indices.contains_key(&entry.key) {
+            return;
+        }
+
+        while clock.memory_usage + entry_memory_usage > self.memory_limit {
+            clock.evict();
+        }
+
+        let index = clock.entries.len();
+        clock.indices.insert(entry.key, index);
+        clock.memory_usage += entry_memory_usage;
+        clock.entries.push(entry);
+    }
+}
+
+/// An evaluator looking histories up in a cache before evaluating them with another evaluator,
+/// which only evaluates the histories missing from the cache. The cache may be shared with other
+/// evaluators, to reuse evaluations between them.
+pub struct CachedEvaluator<E> {
+    evaluator: E,
+    cache: Arc<EvaluationCache>,
+}
+
+impl<E: Evaluator> CachedEvaluator<E> {
+    pub fn new(evaluator: E, cache: Arc<EvaluationCache>) -> Self {
+        Self { evaluator, cache }
+    }
+
+    pub fn cache(&self) -> &Arc<EvaluationCache> {
+        &self.cache
+    }
+}
+
+impl<E: Evaluator> Evaluator for CachedEvaluator<E> {
+    fn move_history(&self) -> usize {
+        self.evaluator.move_history()
+    }
+
+    fn evaluate_batch(&self, histories: Vec<&[HistoryBoard]>) -> Vec<PisaResult> {
+        let mut results = histories
+            .iter()
+            .map(|boards| self.cache.get(boards))
+            .collect::<Vec<_>>();
+
+        let missing_indices = results
+            .iter()
+            .enumerate()
+            .filter_map(|(index, result)| result.is_none().then_some(index))
+            .collect::<Vec<_>>();
+
+        if missing_indices.is_empty() {
+            return results.into_iter().flatten().collect();
+        }
+
+        let evaluated_results = self.evaluator.evaluate_batch(
+            missing_indices
+                .iter()
+                .map(|&index| histories[index])
+                .collect(),
+        );
+
+        for (index, result) in missing_indices.into_iter().zip(evaluated_results) {
+            self.cache.insert(histories[index], &result);
+            results[index] = Some(result);
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.expect("evaluator returned too few results"))
+            .collect()
+    }
+}
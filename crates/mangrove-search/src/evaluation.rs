@@ -0,0 +1,223 @@
+//! Evaluation of the leaves of trees: evaluators, and batching of their evaluations, where leaves
+//! selected by any number of search threads are queued, and evaluated together by a single
+//! evaluation thread.
+
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use burn::tensor::backend::Backend;
+use mangrove_core::{board::Board, mg, repr::Player};
+use mangrove_pisa::{HistoryBoard, MoveProbabilities, Pisa, PisaResult};
+
+/// The material difference, in pawns, by which the material evaluator divides differences before
+/// squashing them between -1 and 1.
+const MATERIAL_SCALE: f32 = 8.0;
+
+/// The values of pawns, knights, bishops, rooks and queens, in pawns.
+const PIECE_VALUES: [f32; 5] = [1.0, 3.0, 3.0, 5.0, 9.0];
+
+/// Evaluates batches of histories of boards, each with the value of its last board for its player
+/// to move, and the probabilities of its moves.
+pub trait Evaluator: Send + 'static {
+    /// The number of boards expected in each history.
+    fn move_history(&self) -> usize;
+
+    fn evaluate_batch(&self, histories: Vec<&[HistoryBoard]>) -> Vec<PisaResult>;
+}
+
+impl<B: Backend> Evaluator for Pisa<B> {
+    fn move_history(&self) -> usize {
+        self.move_history()
+    }
+
+    fn evaluate_batch(&self, histories: Vec<&[HistoryBoard]>) -> Vec<PisaResult> {
+        self.process(histories)
+    }
+}
+
+// Spreads the probabilities evenly between the moves of the board
+fn uniform_move_probabilities(board: &Board) -> MoveProbabilities {
+    let moves = mg::gen_moves(board);
+    let probability = 1.0 / moves.len().max(1) as f32;
+
+    MoveProbabilities::new(
+        moves
+            .into_iter()
+            .map(|chess_move| (probability, chess_move)),
+    )
+}
+
+/// An evaluator valuing every board as even, with the same probability for every move. It is a
+/// baseline for other evaluators, and makes the search testable without a network.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UniformEvaluator;
+
+impl Evaluator for UniformEvaluator {
+    fn move_history(&self) -> usize {
+        1
+    }
+
+    fn evaluate_batch(&self, histories: Vec<&[HistoryBoard]>) -> Vec<PisaResult> {
+        histories
+            .into_iter()
+            .map(|boards| PisaResult {
+                value: 0.0,
+                move_probabilities: uniform_move_probabilities(&boards.last().unwrap().board),
+            })
+            .collect()
+    }
+}
+
+/// An evaluator valuing boards by the material difference between the players, with the same
+/// probability for every move.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaterialEvaluator;
+
+impl MaterialEvaluator {
+    fn material(player: &Player) -> f32 {
+        [
+            player.pawns,
+            player.knights,
+            player.bishops,
+            player.rooks,
+            player.queens,
+        ]
+        .into_iter()
+        .zip(PIECE_VALUES)
+        .map(|(pieces, value)| pieces.count_ones() as f32 * value)
+        .sum()
+    }
+}
+
+impl Evaluator for MaterialEvaluator {
+    fn move_history(&self) -> usize {
+        1
+    }
+
+    fn evaluate_batch(&self, histories: Vec<&[HistoryBoard]>) -> Vec<PisaResult> {
+        histories
+            .into_iter()
+            .map(|boards| {
+                let board = &boards.last().unwrap().board;
+                let material_difference = Self::material(&board.us) - Self::material(&board.them);
+
+                PisaResult {
+                    value: (material_difference / MATERIAL_SCALE).tanh(),
+                    move_probabilities: uniform_move_probabilities(board),
+                }
+            })
+            .collect()
+    }
+}
+
+/// How leaves are gathered into batches: batches are evaluated once they hold `batch_size` leaves,
+/// or once `timeout` has passed since their first leaf was queued, whichever comes first.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchParameters {
+    pub batch_size: usize,
+    pub timeout: Duration,
+}
+
+/// Evaluates the leaves selected while growing a tree, one at a time. Evaluators evaluate leaves
+/// directly, as batches of a single history, and evaluation queues batch them with the leaves of
+/// other threads.
+pub trait LeafEvaluator {
+    /// The number of boards expected in each history.
+    fn move_history(&self) -> usize;
+
+    /// Evaluates the last board of a history, blocking until it is evaluated.
+    fn evaluate(&self, boards: Box<[HistoryBoard]>) -> PisaResult;
+}
+
+impl<E: Evaluator> LeafEvaluator for E {
+    fn move_history(&self) -> usize {
+        Evaluator::move_history(self)
+    }
+
+    fn evaluate(&self, boards: Box<[HistoryBoard]>) -> PisaResult {
+        self.evaluate_batch(vec![&boards]).pop().unwrap()
+    }
+}
+
+struct EvaluationRequest {
+    boards: Box<[HistoryBoard]>,
+    result_sender: Sender<PisaResult>,
+}
+
+/// A handle to the queue of an evaluation thread. Handles may be cloned and shared between
+/// threads, and the evaluation thread stops once every handle is dropped.
+#[derive(Clone)]
+pub struct EvaluationQueue {
+    request_sender: Sender<EvaluationRequest>,
+    move_history: usize,
+}
+
+impl EvaluationQueue {
+    /// Starts an evaluation thread, which evaluates queued leaves with the passed evaluator.
+    pub fn start(evaluator: impl Evaluator, batch_parameters: BatchParameters) -> Self {
+        let (request_sender, request_receiver) = mpsc::channel();
+        let move_history = Evaluator::move_history(&evaluator);
+
+        thread::spawn(move || {
+            while let Some(batch) = Self::gather_batch(&request_receiver, batch_parameters) {
+                tracing::trace!(batch_size = batch.len(), "evaluating batch");
+
+                let results = evaluator
+                    .evaluate_batch(batch.iter().map(|request| &request.boards[..]).collect());
+
+                for (request, result) in batch.into_iter().zip(results) {
+                    // The requesting thread no longer waiting for its result is not an error
+                    let _ = request.result_sender.send(result);
+                }
+            }
+        });
+
+        Self {
+            request_sender,
+            move_history,
+        }
+    }
+
+    // Blocks until a request is queued, and then gathers requests until the batch is full or times
+    // out. Returns `None` once every handle to the queue is dropped.
+    fn gather_batch(
+        request_receiver: &Receiver<EvaluationRequest>,
+        batch_parameters: BatchParameters,
+    ) -> Option<Vec<EvaluationRequest>> {
+        let mut batch = vec![request_receiver.recv().ok()?];
+        let deadline = Instant::now() + batch_parameters.timeout;
+
+        while batch.len() < batch_parameters.batch_size {
+            match request_receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(request) => batch.push(request),
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        Some(batch)
+    }
+}
+
+impl LeafEvaluator for EvaluationQueue {
+    fn move_history(&self) -> usize {
+        self.move_history
+    }
+
+    /// Queues the history of a leaf, and blocks until it is evaluated as part of a batch.
+    fn evaluate(&self, boards: Box<[HistoryBoard]>) -> PisaResult {
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        self.request_sender
+            .send(EvaluationRequest {
+                boards,
+                result_sender,
+            })
+            .expect("evaluation thread stopped");
+
+        result_receiver.recv().expect("evaluation thread stopped")
+    }
+}
@@ -0,0 +1,71 @@
+//! Exploration for self-play: Dirichlet noise mixed into the priors of the moves of the root, and
+//! moves sampled from the visits of the root, with a temperature scheduled by the ply of the game.
+
+use rand::{distributions::WeightedIndex, Rng};
+use rand_distr::{Distribution, Gamma};
+
+/// Dirichlet noise mixed into the priors of the moves of the root, each of which becomes
+/// `(1 - epsilon) * prior + epsilon * noise`.
+#[derive(Debug, Clone, Copy)]
+pub struct DirichletNoise {
+    pub alpha: f32,
+    pub epsilon: f32,
+}
+
+impl DirichletNoise {
+    /// Mixes noise into the passed priors.
+    pub fn apply(&self, priors: &mut [f32], rng: &mut impl Rng) {
+        // Normalized gamma samples are Dirichlet samples
+        let gamma = Gamma::new(self.alpha, 1.0).expect("alpha must be positive");
+        let noise = priors
+            .iter()
+            .map(|_| gamma.sample(rng))
+            .collect::<Vec<f32>>();
+        let noise_sum = noise.iter().sum::<f32>();
+
+        // Every sample may underflow with small alphas
+        if noise_sum <= 0.0 {
+            return;
+        }
+
+        for (prior, noise) in priors.iter_mut().zip(noise) {
+            *prior = (1.0 - self.epsilon) * *prior + self.epsilon * noise / noise_sum;
+        }
+    }
+}
+
+/// The temperature moves are sampled with, by ply of the game: `temperature` before
+/// `cutoff_ply`, and `final_temperature` from then on. Moves are sampled in proportion to their
+/// visits raised to the inverse of the temperature, so a temperature of zero always plays the best
+/// move, which the default schedule does.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TemperatureSchedule {
+    pub temperature: f32,
+    pub cutoff_ply: u32,
+    pub final_temperature: f32,
+}
+
+impl TemperatureSchedule {
+    pub fn temperature(&self, ply: u32) -> f32 {
+        if ply < self.cutoff_ply {
+            self.temperature
+        } else {
+            self.final_temperature
+        }
+    }
+}
+
+/// Samples the index of a move from the visits of every move, with the passed temperature, which
+/// must be positive. Returns `None` if no move was visited.
+pub(crate) fn sample_visits(visits: &[u32], temperature: f32, rng: &mut impl Rng) -> Option<usize> {
+    let max_visits = *visits.iter().max()?;
+
+    // Visits are scaled down first, so low temperatures don't overflow the weights
+    let weights =
+        WeightedIndex::new(visits.iter().map(|&visits| {
+            (visits as f64 / max_visits.max(1) as f64).powf(1.0 / temperature as f64)
+        }))
+        .ok()?;
+
+    Some(rng.sample(weights))
+}
@@ -1,3 +1,1103 @@
-mod puct;
+pub mod cache;
+pub mod evaluation;
+pub mod exploration;
 pub mod search;
+pub mod selection;
 pub mod tree;
+
+// Only used by the benchmarks
+#[cfg(test)]
+use criterion as _;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        str::FromStr,
+        sync::{mpsc, Arc},
+        thread,
+        time::Duration,
+    };
+
+    use burn_ndarray::NdArray;
+
+    use mangrove_core::{board::Board, mg, repr::ChessMove};
+    use mangrove_pisa::{HistoryBoard, PisaConfig, PisaResult};
+    use rand::{rngs::StdRng, SeedableRng};
+    use test_case::test_case;
+
+    use crate::{
+        cache::{CachedEvaluator, EvaluationCache},
+        evaluation::{
+            BatchParameters, EvaluationQueue, Evaluator, MaterialEvaluator, UniformEvaluator,
+        },
+        exploration::{DirichletNoise, TemperatureSchedule},
+        search::{self, RunningSearch, SearchCommand, SearchLimits, SearchParameters, StopReason},
+        selection::{
+            ChildStatistics, FirstPlayUrgency, LogPuct, ParentStatistics, Puct, SelectionPolicy,
+            VariancePuct,
+        },
+        tree::{self, AdvanceTreeError, ProvenResult, RootStatistics, Tree},
+    };
+
+    #[test_case("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"; "back rank")]
+    #[test_case("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1", "a8a1"; "back rank for black")]
+    #[test_case("k7/8/1K6/8/8/8/7Q/8 w - - 0 1", "h2h8"; "queen and king")]
+    fn mate_in_one_tests(fen: &str, mating_move: &str) {
+        let board = Board::from_str(fen).unwrap();
+        let mating_move = ChessMove::from_str(mating_move).unwrap();
+
+        assert_eq!(tree::terminal_result(&board, &[board.hash]), None);
+
+        let mut mated_board = board;
+        mated_board.make_move(mating_move).unwrap();
+
+        assert_eq!(
+            tree::terminal_result(&mated_board, &[board.hash, mated_board.hash]),
+            Some(ProvenResult::Loss)
+        );
+    }
+
+    #[test_case("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"; "stalemate")]
+    #[test_case("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"; "cornered king")]
+    #[test_case("8/8/4k3/8/8/3K4/8/8 w - - 0 1"; "insufficient material")]
+    #[test_case("8/8/4k3/8/8/3K4/8/R7 b - - 100 80"; "fifty move rule")]
+    fn draw_tests(fen: &str) {
+        let board = Board::from_str(fen).unwrap();
+
+        assert_eq!(
+            tree::terminal_result(&board, &[board.hash]),
+            Some(ProvenResult::Draw)
+        );
+    }
+
+    #[test]
+    fn repetition_test() {
+        let mut board = Board::starting_position();
+        let mut hashes = vec![board.hash];
+
+        for chess_move in ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(2) {
+            assert_eq!(tree::terminal_result(&board, &hashes), None);
+
+            board
+                .make_move(ChessMove::from_str(chess_move).unwrap())
+                .unwrap();
+            hashes.push(board.hash);
+        }
+
+        assert_eq!(
+            tree::terminal_result(&board, &hashes),
+            Some(ProvenResult::Draw)
+        );
+    }
+
+    fn uniform_moves(board: &Board) -> Vec<(f32, ChessMove)> {
+        mg::gen_moves(board)
+            .into_iter()
+            .map(|chess_move| (1.0, chess_move))
+            .collect()
+    }
+
+    fn root_children(tree: &Tree) -> Vec<(usize, ChessMove)> {
+        tree.get_children_metadata(&tree.root())
+            .unwrap()
+            .map(|(child_index, child_metadata)| (child_index, child_metadata.chess_move()))
+            .collect()
+    }
+
+    fn visit(tree: &Tree, node_index: usize, visits: u32) {
+        for _ in 0..visits {
+            tree.add_virtual_loss(node_index);
+
+            tree.backpropagate(0.0, &[node_index]);
+        }
+    }
+
+    #[test]
+    fn backpropagation_test() {
+        let board = Board::starting_position();
+        let tree = Tree::new(board);
+
+        tree.expand(0, 0.0, &uniform_moves(&board));
+
+        let (child_index, child_metadata) = tree
+            .get_children_metadata(&tree.root())
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let mut child_board = board;
+        child_board.make_move(child_metadata.chess_move()).unwrap();
+
+        tree.expand(child_index, 0.0, &uniform_moves(&child_board));
+
+        let grandchild_index = tree
+            .get_children_metadata(&tree.get(child_index))
+            .unwrap()
+            .next()
+            .unwrap()
+            .0;
+
+        let path = [child_index, grandchild_index];
+
+        for &node_index in &path {
+            tree.add_virtual_loss(node_index);
+        }
+
+        // The leaf is good for its player to move, so the move leading to it is bad for the
+        // player who made it, and the move before that is good for its player
+        tree.backpropagate(0.5, &path);
+
+        let value_sum = |parent_index, node_index| {
+            tree.get_children_metadata(&tree.get(parent_index))
+                .unwrap()
+                .find(|&(index, _)| index == node_index)
+                .unwrap()
+                .1
+                .value_sum
+        };
+
+        assert_eq!(value_sum(0, child_index), 0.5);
+        assert_eq!(value_sum(child_index, grandchild_index), -0.5);
+    }
+
+    #[test]
+    fn proven_win_test() {
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mating_move = ChessMove::from_str("a1a8").unwrap();
+        let tree = Tree::new(board);
+
+        tree.expand(0, 0.0, &uniform_moves(&board));
+
+        let children = root_children(&tree);
+        let &(mating_index, _) = children
+            .iter()
+            .find(|&&(_, chess_move)| chess_move == mating_move)
+            .unwrap();
+        let &(other_index, _) = children
+            .iter()
+            .find(|&&(_, chess_move)| chess_move != mating_move)
+            .unwrap();
+
+        visit(&tree, other_index, 10);
+
+        tree.mark_proven(mating_index, ProvenResult::Loss);
+        tree.propagate_proofs(&[0, mating_index]);
+
+        assert_eq!(tree.root_result(), Some(ProvenResult::Win));
+        assert_eq!(tree.best_move(), Some(mating_move));
+    }
+
+    #[test_case(ProvenResult::Draw, ProvenResult::Draw; "drawn")]
+    #[test_case(ProvenResult::Win, ProvenResult::Loss; "lost")]
+    fn all_children_proven_test(last_child_result: ProvenResult, root_result: ProvenResult) {
+        let board = Board::starting_position();
+        let tree = Tree::new(board);
+
+        tree.expand(0, 0.0, &uniform_moves(&board));
+
+        let children = root_children(&tree);
+        let (losing_index, losing_move) = children[0];
+        let (last_index, last_move) = children[1];
+
+        // Proven losses are avoided, however much they were visited
+        visit(&tree, losing_index, 10);
+
+        tree.mark_proven(losing_index, ProvenResult::Win);
+        tree.propagate_proofs(&[0, losing_index]);
+
+        assert_eq!(tree.root_result(), None);
+        assert_ne!(tree.best_move(), Some(losing_move));
+
+        for &(child_index, _) in &children[2..] {
+            tree.mark_proven(child_index, ProvenResult::Win);
+            tree.propagate_proofs(&[0, child_index]);
+        }
+
+        assert_eq!(tree.root_result(), None);
+
+        tree.mark_proven(last_index, last_child_result);
+        tree.propagate_proofs(&[0, last_index]);
+
+        assert_eq!(tree.root_result(), Some(root_result));
+
+        if last_child_result == ProvenResult::Draw {
+            assert_eq!(tree.best_move(), Some(last_move));
+        }
+    }
+
+    fn play(chess_moves: &[&str]) -> (Board, Vec<u64>) {
+        let mut board = Board::starting_position();
+        let mut hashes = vec![board.hash];
+
+        for chess_move in chess_moves {
+            board
+                .make_move(ChessMove::from_str(chess_move).unwrap())
+                .unwrap();
+            hashes.push(board.hash);
+        }
+
+        (board, hashes)
+    }
+
+    #[test_case(&["e2e3", "e7e6", "d2d3"], &["d2d3", "e7e6", "e2e3"]; "pawn moves")]
+    #[test_case(&["e2e3", "g8f6", "d2d3"], &["d2d3", "g8f6", "e2e3"]; "knight move between")]
+    #[test_case(&["g1f3", "g8f6", "b1c3"], &["b1c3", "g8f6", "g1f3"]; "reversible moves")]
+    #[test_case(&["g1f3", "g8f6", "b1c3"], &["g1f3", "b8c6", "b1c3", "c6b8", "c3b1", "g8f6", "b1c3"]; "different earlier positions")]
+    fn transposition_tests(chess_moves: &[&str], transposed_chess_moves: &[&str]) {
+        let (board, hashes) = play(chess_moves);
+        let (transposed_board, transposed_hashes) = play(transposed_chess_moves);
+
+        assert_eq!(
+            tree::transposition_key(&board, &hashes),
+            tree::transposition_key(&transposed_board, &transposed_hashes)
+        );
+    }
+
+    #[test_case(true, 1; "shared")]
+    #[test_case(false, 2; "not shared")]
+    fn transposition_search_tests(transpositions: bool, expected_evaluations: u64) {
+        let board = Board::starting_position();
+        let tree = Tree::new(board).with_transpositions(transpositions);
+
+        // Both moves of the root reach the same board two plies later
+        let lines = [["g1f3", "g8f6", "b1c3"], ["b1c3", "g8f6", "g1f3"]];
+
+        tree.expand(
+            0,
+            0.0,
+            &lines.map(|line| (1.0, ChessMove::from_str(line[0]).unwrap())),
+        );
+
+        for ((child_index, _), line) in root_children(&tree).into_iter().zip(lines) {
+            expand_line(&tree, child_index, &line[1..]);
+        }
+
+        // The first playout evaluates the board, and the second reaches it through the other move
+        for _ in 0..2 {
+            assert!(tree.grow(&UniformEvaluator));
+        }
+
+        assert_eq!(tree.evaluations(), expected_evaluations);
+        assert!(tree
+            .move_statistics()
+            .iter()
+            .all(|move_statistics| move_statistics.visits == 1));
+    }
+
+    // The boards are the same, but only one of them occurred before
+    #[test_case(&[], &["g1f3", "g8f6", "f3g1", "f6g8"]; "repeated starting position")]
+    #[test_case(&["e2e3", "e7e6"], &["e2e3", "e7e6", "g1f3", "b8c6", "f3g1", "c6b8"]; "repeated after pawn moves")]
+    fn repetition_transposition_tests(chess_moves: &[&str], repeating_chess_moves: &[&str]) {
+        let (board, hashes) = play(chess_moves);
+        let (repeating_board, repeating_hashes) = play(repeating_chess_moves);
+
+        assert_eq!(board.hash, repeating_board.hash);
+        assert_ne!(
+            tree::transposition_key(&board, &hashes),
+            tree::transposition_key(&repeating_board, &repeating_hashes)
+        );
+    }
+
+    #[test]
+    fn principal_variation_test() {
+        let board = Board::starting_position();
+        let tree = Tree::new(board);
+
+        tree.expand(0, 0.0, &uniform_moves(&board));
+
+        let children = root_children(&tree);
+        let (best_index, best_move) = children[1];
+
+        let mut best_board = board;
+        best_board.make_move(best_move).unwrap();
+
+        tree.expand(best_index, 0.0, &uniform_moves(&best_board));
+
+        let (grandchild_index, grandchild_metadata) = tree
+            .get_children_metadata(&tree.get(best_index))
+            .unwrap()
+            .next()
+            .unwrap();
+        let path = [best_index, grandchild_index];
+
+        for _ in 0..2 {
+            for &node_index in &path {
+                tree.add_virtual_loss(node_index);
+            }
+
+            tree.backpropagate(0.5, &path);
+        }
+
+        visit(&tree, children[0].0, 1);
+
+        assert_eq!(
+            tree.principal_variation(),
+            [best_move, grandchild_metadata.chess_move()]
+        );
+
+        let move_statistics = tree.move_statistics();
+
+        assert_eq!(move_statistics.len(), children.len());
+        assert_eq!(move_statistics[0].chess_move, best_move);
+        assert_eq!(move_statistics[0].visits, 2);
+        assert_eq!(move_statistics[0].q_value, Some(0.5));
+        assert_eq!(move_statistics[1].visits, 1);
+        assert_eq!(move_statistics[2].q_value, None);
+    }
+
+    #[test]
+    fn compaction_test() {
+        let board = Board::starting_position();
+        let mut tree = Tree::new(board);
+
+        tree.expand(0, 0.0, &uniform_moves(&board));
+
+        let children = root_children(&tree);
+
+        // The second child is expanded too, so its subtree has to be discarded
+        for &(child_index, chess_move) in &children[..2] {
+            let mut child_board = board;
+            child_board.make_move(chess_move).unwrap();
+
+            tree.expand(child_index, 0.0, &uniform_moves(&child_board));
+        }
+
+        let (kept_index, kept_move) = children[0];
+        let (grandchild_index, grandchild_metadata) = tree
+            .get_children_metadata(&tree.get(kept_index))
+            .unwrap()
+            .next()
+            .unwrap();
+        let path = [kept_index, grandchild_index];
+
+        for &node_index in &path {
+            tree.add_virtual_loss(node_index);
+        }
+
+        tree.backpropagate(0.5, &path);
+
+        tree.try_advance(kept_move).unwrap();
+
+        let mut kept_board = board;
+        kept_board.make_move(kept_move).unwrap();
+
+        assert_eq!(tree.node_count(), 1 + mg::gen_moves(&kept_board).len());
+
+        let (_, kept_grandchild_metadata) = tree
+            .get_children_metadata(&tree.root())
+            .unwrap()
+            .find(|(_, metadata)| metadata.chess_move() == grandchild_metadata.chess_move())
+            .unwrap();
+
+        assert_eq!(kept_grandchild_metadata.visits, 1);
+        assert_eq!(kept_grandchild_metadata.value_sum, -0.5);
+    }
+
+    #[test]
+    fn memory_limit_test() {
+        let board = Board::starting_position();
+        let single_node_memory = Tree::new(board).memory_usage();
+        let mut tree = Tree::new(board).with_memory_limit(single_node_memory * 10);
+
+        assert!(!tree.is_full());
+
+        tree.expand(0, 0.0, &uniform_moves(&board));
+
+        assert!(tree.is_full());
+
+        tree.try_advance(root_children(&tree)[0].1).unwrap();
+
+        assert!(!tree.is_full());
+    }
+
+    #[test]
+    fn root_noise_test() {
+        let board = Board::starting_position();
+        let moves = mg::gen_moves(&board)
+            .into_iter()
+            .map(|chess_move| (0.05, chess_move))
+            .collect::<Vec<_>>();
+        let tree = Tree::new(board).with_root_noise(DirichletNoise {
+            alpha: 0.3,
+            epsilon: 0.25,
+        });
+
+        tree.expand(0, 0.0, &moves);
+
+        let priors = tree
+            .move_statistics()
+            .iter()
+            .map(|move_statistics| move_statistics.prior)
+            .collect::<Vec<_>>();
+
+        assert!((priors.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+        assert!(priors.iter().all(|&prior| prior >= 0.75 * 0.05));
+        assert!(priors.iter().any(|&prior| prior != 0.05));
+    }
+
+    #[test_case(0, 1.0; "opening")]
+    #[test_case(29, 1.0; "last ply before cutoff")]
+    #[test_case(30, 0.0; "cutoff")]
+    fn temperature_schedule_tests(ply: u32, expected_temperature: f32) {
+        let temperature_schedule = TemperatureSchedule {
+            temperature: 1.0,
+            cutoff_ply: 30,
+            final_temperature: 0.0,
+        };
+
+        assert_eq!(temperature_schedule.temperature(ply), expected_temperature);
+    }
+
+    #[test]
+    fn sample_move_test() {
+        let board = Board::starting_position();
+        let tree = Tree::new(board);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        tree.expand(0, 0.0, &uniform_moves(&board));
+
+        let children = root_children(&tree);
+
+        visit(&tree, children[0].0, 1);
+        visit(&tree, children[1].0, 3);
+
+        assert_eq!(tree.sample_move(0.0, &mut rng), Some(children[1].1));
+
+        // Unvisited moves are never sampled
+        for _ in 0..100 {
+            let sampled_move = tree.sample_move(1.0, &mut rng).unwrap();
+
+            assert!(sampled_move == children[0].1 || sampled_move == children[1].1);
+        }
+    }
+
+    const PARENT: ParentStatistics = ParentStatistics {
+        visits: 16,
+        value: 0.2,
+    };
+
+    #[test]
+    fn puct_test() {
+        let puct = Puct {
+            exploration_rate: 2.0,
+            first_play_urgency: FirstPlayUrgency::Reduction(0.3),
+        };
+
+        let visited_child = ChildStatistics {
+            visits: 3,
+            value_sum: 1.5,
+            value_square_sum: 0.75,
+            prior: 0.5,
+        };
+        let unvisited_child = ChildStatistics {
+            visits: 0,
+            value_sum: 0.0,
+            value_square_sum: 0.0,
+            prior: 0.5,
+        };
+
+        // Q + c * P * sqrt(N) / (1 + n)
+        assert!((puct.score(&PARENT, &visited_child) - (0.5 + 2.0 * 0.5 * 4.0 / 4.0)).abs() < 1e-6);
+        assert!((puct.score(&PARENT, &unvisited_child) - (-0.1 + 2.0 * 0.5 * 4.0)).abs() < 1e-6);
+    }
+
+    #[test_case(&Puct::default(); "puct")]
+    #[test_case(&LogPuct::default(); "log puct")]
+    #[test_case(&VariancePuct::default(); "variance puct")]
+    fn selection_policy_tests(selection_policy: &dyn SelectionPolicy) {
+        let child = |visits, value_sum: f32| ChildStatistics {
+            visits,
+            value_sum,
+            value_square_sum: value_sum * value_sum / visits as f32,
+            prior: 0.1,
+        };
+
+        // Better scoring children are preferred, and so are less visited ones
+        assert!(
+            selection_policy.score(&PARENT, &child(4, 2.0))
+                > selection_policy.score(&PARENT, &child(4, -2.0))
+        );
+        assert!(
+            selection_policy.score(&PARENT, &child(2, 1.0))
+                > selection_policy.score(&PARENT, &child(8, 4.0))
+        );
+
+        // Pending selections count as losses, so value sums may be negative
+        assert!(selection_policy.score(&PARENT, &child(4, -4.0)).is_finite());
+    }
+
+    #[test]
+    fn fresh_root_test() {
+        let board = Board::starting_position();
+        let tree = Tree::new(board);
+
+        assert!(tree.grow(&UniformEvaluator));
+        assert_eq!(tree.node_count(), 1 + mg::gen_moves(&board).len());
+        assert_eq!(tree.root_statistics().visits, 1);
+    }
+
+    #[test]
+    fn playouts_test() {
+        let tree = Tree::new(Board::starting_position());
+
+        for _ in 0..100 {
+            tree.grow(&UniformEvaluator);
+        }
+
+        let children = tree
+            .get_children_metadata(&tree.root())
+            .unwrap()
+            .map(|(_, child_metadata)| child_metadata)
+            .collect::<Vec<_>>();
+
+        // Every playout but the one expanding the root goes through one of its children
+        assert_eq!(tree.root_statistics().visits, 100);
+        assert_eq!(
+            children
+                .iter()
+                .map(|child_metadata| child_metadata.visits)
+                .sum::<u32>(),
+            99
+        );
+        assert!(children
+            .iter()
+            .all(|child_metadata| child_metadata.virtual_losses == 0));
+        assert!(tree.depth() >= 2);
+    }
+
+    #[test]
+    fn parallel_playouts_test() {
+        let tree = Tree::new(Board::starting_position());
+
+        // Every thread starts on the fresh tree, so some of them select its unexpanded root
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..25 {
+                        assert!(tree.grow(&UniformEvaluator));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(tree.root_statistics().visits, 100);
+        assert!(tree
+            .get_children_metadata(&tree.root())
+            .unwrap()
+            .all(|(_, child_metadata)| child_metadata.virtual_losses == 0));
+    }
+
+    #[test]
+    fn queued_playouts_test() {
+        let tree = Tree::new(Board::starting_position());
+        let evaluation_queue = EvaluationQueue::start(
+            UniformEvaluator,
+            BatchParameters {
+                batch_size: 4,
+                timeout: Duration::from_millis(1),
+            },
+        );
+
+        // The leaves of the threads are evaluated together, starting with the unexpanded root
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..25 {
+                        assert!(tree.grow(&evaluation_queue));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(tree.root_statistics().visits, 100);
+        assert!(tree
+            .get_children_metadata(&tree.root())
+            .unwrap()
+            .all(|(_, child_metadata)| child_metadata.virtual_losses == 0));
+    }
+
+    #[test_case("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"; "back rank")]
+    #[test_case("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1", "a8a1"; "back rank for black")]
+    fn mate_in_one_search_tests(fen: &str, mating_move: &str) {
+        let tree = Tree::new(Board::from_str(fen).unwrap());
+
+        for _ in 0..256 {
+            tree.grow(&UniformEvaluator);
+        }
+
+        assert_eq!(tree.root_result(), Some(ProvenResult::Win));
+        assert_eq!(
+            tree.best_move(),
+            Some(ChessMove::from_str(mating_move).unwrap())
+        );
+    }
+
+    #[test_case("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", ProvenResult::Loss; "checkmate")]
+    #[test_case("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", ProvenResult::Draw; "stalemate")]
+    #[test_case("8/8/4k3/8/8/3K4/8/8 w - - 0 1", ProvenResult::Draw; "insufficient material")]
+    fn terminal_root_search_tests(fen: &str, root_result: ProvenResult) {
+        let tree = Tree::new(Board::from_str(fen).unwrap());
+
+        for _ in 0..8 {
+            assert!(tree.grow(&UniformEvaluator));
+        }
+
+        // The root is scored exactly on every playout, and never evaluated
+        assert_eq!(tree.root_result(), Some(root_result));
+        assert_eq!(tree.root_statistics().visits, 8);
+        assert_eq!(tree.evaluations(), 0);
+        assert_eq!(tree.best_move(), None);
+    }
+
+    // Expands a line of single moves from a node, which every playout through it then follows
+    fn expand_line(tree: &Tree, mut node_index: usize, chess_moves: &[&str]) {
+        for chess_move in chess_moves {
+            tree.expand(
+                node_index,
+                0.0,
+                &[(1.0, ChessMove::from_str(chess_move).unwrap())],
+            );
+
+            node_index = tree
+                .get_children_metadata(&tree.get(node_index))
+                .unwrap()
+                .next()
+                .unwrap()
+                .0;
+        }
+    }
+
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8"]; "threefold repetition")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8", "e2e4"]; "threefold repetition of expanded node")]
+    #[test_case("8/8/4k3/8/8/3K4/8/R7 b - - 99 80", &["e6e5"]; "fifty move rule")]
+    fn history_draw_search_tests(fen: &str, chess_moves: &[&str]) {
+        let tree = Tree::new(Board::from_str(fen).unwrap());
+
+        expand_line(&tree, 0, chess_moves);
+
+        let node_count = tree.node_count();
+
+        for _ in 0..4 {
+            assert!(tree.grow(&UniformEvaluator));
+        }
+
+        // The drawn node is scored as a draw by every playout, but isn't proven, as other paths
+        // may reach it without drawing, so neither is the line leading to it
+        assert_eq!(tree.node_count(), node_count);
+        assert_eq!(tree.evaluations(), 0);
+        assert_eq!(tree.root_result(), None);
+        assert_eq!(tree.move_statistics()[0].q_value, Some(0.0));
+    }
+
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 0.0; "even")]
+    #[test_case("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 9.0; "queen up")]
+    #[test_case("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1", -9.0; "queen down")]
+    fn material_evaluator_tests(fen: &str, material_difference: f32) {
+        let board = Board::from_str(fen).unwrap();
+        let result = MaterialEvaluator
+            .evaluate_batch(vec![&[board.into()]])
+            .pop()
+            .unwrap();
+
+        assert!((result.value - (material_difference / 8.0).tanh()).abs() < 1e-6);
+
+        let moves = mg::gen_moves(&board);
+
+        for &chess_move in &moves {
+            assert_eq!(
+                result.move_probabilities[chess_move],
+                1.0 / moves.len() as f32
+            );
+        }
+    }
+
+    #[test]
+    fn material_search_test() {
+        // Black's queen is hanging to the bishop
+        let board =
+            Board::from_str("rnb1kbnr/pppp1ppp/8/4p1q1/8/3P4/PPP1PPPP/RNBQKBNR w KQkq - 0 1")
+                .unwrap();
+        let tree = Tree::new(board);
+
+        for _ in 0..512 {
+            tree.grow(&MaterialEvaluator);
+        }
+
+        assert_eq!(tree.best_move(), Some(ChessMove::from_str("c1g5").unwrap()));
+    }
+
+    #[test]
+    fn cache_hit_test() {
+        let cache = Arc::new(EvaluationCache::new(1 << 20));
+        let evaluator = CachedEvaluator::new(MaterialEvaluator, cache.clone());
+
+        let mut board = Board::starting_position();
+        board
+            .make_move(ChessMove::from_str("e2e4").unwrap())
+            .unwrap();
+        let history = [Board::starting_position().into(), board.into()];
+
+        let missed_result = evaluator.evaluate_batch(vec![&history]).pop().unwrap();
+        let hit_results = evaluator.evaluate_batch(vec![&history, &history]);
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.statistics().hits, 2);
+        assert_eq!(cache.statistics().misses, 1);
+        assert_eq!(cache.statistics().hit_rate(), Some(2.0 / 3.0));
+
+        for hit_result in hit_results {
+            assert_eq!(hit_result.value, missed_result.value);
+
+            for chess_move in mg::gen_moves(&board) {
+                assert_eq!(
+                    hit_result.move_probabilities[chess_move],
+                    missed_result.move_probabilities[chess_move]
+                );
+            }
+        }
+
+        // The same board reached through another history is evaluated separately
+        evaluator.evaluate_batch(vec![&history[1..]]);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.statistics().misses, 2);
+
+        // So is the same history with another fifty-move clock, which hashes leave out
+        let mut clocked_board = board;
+        clocked_board.min_ply_clock += 10;
+        evaluator.evaluate_batch(vec![&[history[0], clocked_board.into()]]);
+
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.statistics().misses, 3);
+
+        // And the same history with the last board reached before
+        let repeated_board = HistoryBoard {
+            board,
+            repetitions: 1,
+        };
+        evaluator.evaluate_batch(vec![&[history[0], repeated_board]]);
+
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.statistics().misses, 4);
+    }
+
+    // Evaluates like the uniform evaluator, from histories of the last two boards
+    struct TwoBoardEvaluator;
+
+    impl Evaluator for TwoBoardEvaluator {
+        fn move_history(&self) -> usize {
+            2
+        }
+
+        fn evaluate_batch(&self, histories: Vec<&[HistoryBoard]>) -> Vec<PisaResult> {
+            UniformEvaluator.evaluate_batch(histories)
+        }
+    }
+
+    #[test]
+    fn cache_across_moves_test() {
+        let cache = Arc::new(EvaluationCache::new(1 << 20));
+        let evaluator = CachedEvaluator::new(TwoBoardEvaluator, cache.clone());
+        let searched_tree = Tree::new(Board::starting_position());
+
+        for _ in 0..64 {
+            searched_tree.grow(&evaluator);
+        }
+
+        // The board after the best move was evaluated with the starting position before it, which
+        // another tree advanced to that board still has
+        let mut tree = Tree::new(Board::starting_position());
+        tree.grow(&evaluator);
+        tree.try_advance(searched_tree.best_move().unwrap())
+            .unwrap();
+
+        let misses = cache.statistics().misses;
+        tree.grow(&evaluator);
+
+        assert_eq!(cache.statistics().misses, misses);
+        assert_eq!(tree.evaluations(), 2);
+    }
+
+    #[test]
+    fn selection_repetitions_test() {
+        let mut tree = Tree::new(Board::starting_position());
+
+        for chess_move in ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(2) {
+            tree.grow(&UniformEvaluator);
+            tree.try_advance(ChessMove::from_str(chess_move).unwrap())
+                .unwrap();
+        }
+
+        // The starting position is reached for the third time, though the evaluator only sees the
+        // root
+        let selection = tree.select(1);
+
+        assert_eq!(selection.boards.len(), 1);
+        assert_eq!(
+            selection.boards[0].board.hash,
+            Board::starting_position().hash
+        );
+        assert_eq!(selection.boards[0].repetitions, 2);
+    }
+
+    #[test]
+    fn cache_eviction_test() {
+        let evaluate = |cache: &EvaluationCache, boards: &[HistoryBoard]| {
+            if cache.get(boards).is_none() {
+                let result = UniformEvaluator.evaluate_batch(vec![boards]).pop().unwrap();
+                cache.insert(boards, &result);
+            }
+        };
+
+        let boards = mg::gen_moves(&Board::starting_position())
+            .into_iter()
+            .map(|chess_move| {
+                let mut board = Board::starting_position();
+                board.make_move(chess_move).unwrap();
+                HistoryBoard::from(board)
+            })
+            .collect::<Vec<_>>();
+
+        let single_entry_cache = EvaluationCache::new(1 << 20);
+        evaluate(&single_entry_cache, &boards[..1]);
+
+        // Room for three evaluations
+        let cache = EvaluationCache::new(single_entry_cache.memory_usage() * 7 / 2);
+
+        for board in &boards[..3] {
+            evaluate(&cache, &[*board]);
+        }
+
+        // The first evaluation is used again, so the clock passes over it
+        evaluate(&cache, &boards[..1]);
+        evaluate(&cache, &boards[3..4]);
+
+        assert_eq!(cache.len(), 3);
+        assert!(cache.memory_usage() <= single_entry_cache.memory_usage() * 7 / 2);
+        assert!(cache.get(&boards[..1]).is_some());
+        assert!(cache.get(&boards[1..2]).is_none());
+        assert!(cache.get(&boards[3..4]).is_some());
+
+        for board in &boards {
+            evaluate(&cache, &[*board]);
+        }
+
+        assert_eq!(cache.len(), 3);
+    }
+
+    fn search_parameters() -> SearchParameters {
+        SearchParameters {
+            search_threads: 1,
+            batch_parameters: BatchParameters {
+                batch_size: 1,
+                timeout: Duration::ZERO,
+            },
+            ponder: false,
+            temperature_schedule: TemperatureSchedule {
+                temperature: 0.0,
+                cutoff_ply: 0,
+                final_temperature: 0.0,
+            },
+        }
+    }
+
+    #[test_case(SearchLimits { max_playouts: Some(0), ..Default::default() }, StopReason::MaxPlayouts; "no playouts")]
+    #[test_case(SearchLimits { max_playouts: Some(50), ..Default::default() }, StopReason::MaxPlayouts; "playouts")]
+    #[test_case(SearchLimits { max_nodes: Some(1), ..Default::default() }, StopReason::MaxNodes; "nodes of fresh tree")]
+    #[test_case(SearchLimits { max_nodes: Some(100), ..Default::default() }, StopReason::MaxNodes; "nodes")]
+    #[test_case(SearchLimits { max_time: Some(Duration::ZERO), ..Default::default() }, StopReason::MaxTime; "no time")]
+    fn search_limit_tests(search_limits: SearchLimits, stop_reason: StopReason) {
+        let search_handle = search::start_search_thread(
+            Tree::new(Board::starting_position()),
+            UniformEvaluator,
+            search_parameters(),
+        );
+
+        search_handle
+            .command_sender
+            .send(SearchCommand::Search(search_limits))
+            .unwrap();
+
+        let search_result = search_handle.result_receiver.recv().unwrap();
+        let search_info = search_handle.info_receiver.try_iter().last().unwrap();
+
+        assert_eq!(search_result.stop_reason, stop_reason);
+        assert!(search_result.best_move.is_some());
+
+        // Searches stopped before their first playout still expand the root to pick a move
+        assert_eq!(search_info.root_visits, search_info.playouts.max(1));
+
+        if let Some(max_playouts) = search_limits.max_playouts {
+            assert_eq!(search_info.playouts, max_playouts);
+        }
+
+        if let Some(max_nodes) = search_limits.max_nodes {
+            assert!(search_info.node_count >= max_nodes);
+        }
+    }
+
+    #[test]
+    fn stopped_search_test() {
+        let search_handle = search::start_search_thread(
+            Tree::new(Board::starting_position()),
+            UniformEvaluator,
+            search_parameters(),
+        );
+
+        search_handle
+            .command_sender
+            .send(SearchCommand::Search(SearchLimits::default()))
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        search_handle
+            .command_sender
+            .send(SearchCommand::Stop)
+            .unwrap();
+
+        let search_result = search_handle.result_receiver.recv().unwrap();
+        let search_info = search_handle.info_receiver.try_iter().last().unwrap();
+
+        assert_eq!(search_result.stop_reason, StopReason::Stopped);
+        assert!(search_result.best_move.is_some());
+        assert_eq!(search_info.root_visits, search_info.playouts.max(1));
+
+        // The search played its best move, so the next one searches from the board after it
+        let mut board = Board::starting_position();
+        board.make_move(search_result.best_move.unwrap()).unwrap();
+
+        assert_eq!(search_handle.root_board(), board);
+    }
+
+    #[test]
+    fn unexpanded_played_move_test() {
+        let search_handle = search::start_search_thread(
+            Tree::new(Board::starting_position()),
+            UniformEvaluator,
+            search_parameters(),
+        );
+        let play_move = |chess_move: ChessMove| {
+            let (advance_result_sender, advance_result_receiver) = mpsc::channel();
+
+            search_handle
+                .command_sender
+                .send(SearchCommand::PlayedMove(chess_move, advance_result_sender))
+                .unwrap();
+
+            advance_result_receiver.recv().unwrap()
+        };
+
+        search_handle
+            .command_sender
+            .send(SearchCommand::Search(SearchLimits {
+                max_playouts: Some(0),
+                ..Default::default()
+            }))
+            .unwrap();
+        search_handle.result_receiver.recv().unwrap();
+
+        // Without pondering, the root after the played move is never expanded
+        let board = search_handle.root_board();
+        let opponent_move = mg::gen_moves(&board)[0];
+
+        assert!(play_move(opponent_move).is_ok());
+        assert!(matches!(
+            play_move(ChessMove::from_str("a1a8").unwrap()),
+            Err(AdvanceTreeError::IllegalMove)
+        ));
+
+        // The search thread is still running
+        search_handle
+            .command_sender
+            .send(SearchCommand::Search(SearchLimits {
+                max_playouts: Some(10),
+                ..Default::default()
+            }))
+            .unwrap();
+
+        assert!(search_handle
+            .result_receiver
+            .recv()
+            .unwrap()
+            .best_move
+            .is_some());
+    }
+
+    #[test]
+    fn terminal_root_search_result_test() {
+        let search_handle = search::start_search_thread(
+            Tree::new(Board::from_str("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap()),
+            UniformEvaluator,
+            search_parameters(),
+        );
+
+        search_handle
+            .command_sender
+            .send(SearchCommand::Search(SearchLimits {
+                max_playouts: Some(8),
+                ..Default::default()
+            }))
+            .unwrap();
+
+        let search_result = search_handle.result_receiver.recv().unwrap();
+
+        assert_eq!(search_result.stop_reason, StopReason::MaxPlayouts);
+        assert_eq!(search_result.best_move, None);
+    }
+
+    #[test]
+    fn pondered_gap_test() {
+        let running_search = RunningSearch::new(SearchLimits {
+            max_time: Some(Duration::from_secs(1)),
+            stop_when_unassailable: true,
+            ..Default::default()
+        });
+
+        // Most visits were gathered while pondering, before the search started
+        let root_statistics = RootStatistics {
+            visits: 1000,
+            best_move: Some(ChessMove::from_str("e2e4").unwrap()),
+            best_move_visits: 900,
+            second_best_move_visits: 50,
+        };
+
+        // The speed of the search can't be measured from its first few playouts
+        assert!(!running_search.is_unassailable(&root_statistics, 0, Duration::from_millis(5)));
+        assert!(!running_search.is_unassailable(&root_statistics, 10, Duration::from_millis(5)));
+
+        // At 1000 playouts a second, 500 playouts are left, which can't make up the gap
+        assert!(running_search.is_unassailable(&root_statistics, 500, Duration::from_millis(500)));
+
+        // At 2000 playouts a second, 1000 playouts are left, which can
+        assert!(!running_search.is_unassailable(
+            &root_statistics,
+            1000,
+            Duration::from_millis(500)
+        ));
+    }
+
+    #[test]
+    fn network_search_test() {
+        let network = PisaConfig::small().init::<NdArray>();
+        let evaluation_queue = EvaluationQueue::start(
+            network,
+            BatchParameters {
+                batch_size: 1,
+                timeout: Duration::ZERO,
+            },
+        );
+        let tree = Tree::new(Board::starting_position());
+
+        for _ in 0..32 {
+            tree.grow(&evaluation_queue);
+        }
+
+        assert_eq!(tree.root_statistics().visits, 32);
+
+        for move_statistics in tree.move_statistics() {
+            assert!(move_statistics.prior.is_finite());
+            assert!(move_statistics
+                .q_value
+                .map_or(true, |q_value| q_value.abs() <= 1.0));
+        }
+    }
+}
@@ -1,57 +1,528 @@
-use crate::tree::Tree;
-use burn::tensor::backend::Backend;
-use mangrove_core::repr::ChessMove;
-use mangrove_pisa::Pisa;
+use crate::{
+    evaluation::{BatchParameters, EvaluationQueue, Evaluator},
+    exploration::TemperatureSchedule,
+    tree::{AdvanceTreeError, MoveStatistics, RootStatistics, Tree},
+};
+use mangrove_core::{board::Board, repr::ChessMove};
 
 use std::{
-    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender},
+        Arc, Mutex, RwLock,
+    },
     thread,
+    time::{Duration, Instant},
 };
 
+// How long search threads wait before trying to grow the tree again, when they may not grow it
+const IDLE_BACKOFF: Duration = Duration::from_millis(10);
+
+// How often the limits of a running search are checked
+const LIMIT_CHECK_INTERVAL: Duration = Duration::from_millis(5);
+
+// How long a search must run, and how many playouts it must run, before its speed is measured to
+// estimate the playouts left before its time limit. Until then, the best move is never considered
+// unassailable by time, as the visits kept from pondering may outnumber the playouts of the search.
+const MIN_SPEED_MEASUREMENT_TIME: Duration = Duration::from_millis(50);
+const MIN_SPEED_MEASUREMENT_PLAYOUTS: u32 = 32;
+
+// How often search infos are sent while a search is running
+const INFO_INTERVAL: Duration = Duration::from_secs(1);
+
+// How many search infos may wait to be received before new ones are dropped
+const INFO_CHANNEL_CAPACITY: usize = 16;
+
 pub enum SearchCommand {
-    SendAndPlayBestMove,
-    PlayedMove(ChessMove),
+    /// Searches the root until one of the limits is reached, and then sends and plays the best
+    /// move.
+    Search(SearchLimits),
+    /// Stops the running search as if one of its limits was reached. Ignored when no search is
+    /// running.
+    Stop,
+    /// Advances the tree with a move of the opponent, and sends back whether the move could be
+    /// played from the root.
+    PlayedMove(ChessMove, Sender<Result<(), AdvanceTreeError>>),
+}
+
+/// Bounds on a search, the first of which to be reached stops it. Limits which aren't set don't
+/// bound the search.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchLimits {
+    /// The number of nodes in the tree, including those kept from previous searches.
+    pub max_nodes: Option<usize>,
+    /// The number of playouts of the search. With a single search thread and batches of a single
+    /// leaf, a search bounded by playouts always grows the same tree.
+    pub max_playouts: Option<u32>,
+    pub max_time: Option<Duration>,
+    /// Whether to stop once the second most visited move can no longer overtake the most visited
+    /// one before the playout or time limit.
+    pub stop_when_unassailable: bool,
+}
+
+/// Why a search stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    MaxNodes,
+    MaxPlayouts,
+    MaxTime,
+    Unassailable,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SearchResult {
+    /// The move played from the root, or `None` if the root has no move to play, as the game is
+    /// over in it, or it can't be expanded within the memory limit of the tree.
+    pub best_move: Option<ChessMove>,
+    pub stop_reason: StopReason,
+}
+
+/// A snapshot of a search, for analysis and debugging.
+#[derive(Debug, Clone)]
+pub struct SearchInfo {
+    pub elapsed: Duration,
+    pub playouts: u32,
+    /// Playouts per second, which are the nodes per second of MCTS engines.
+    pub nodes_per_second: f64,
+    pub root_visits: u32,
+    pub node_count: usize,
+    pub depth: usize,
+    pub principal_variation: Vec<ChessMove>,
+    /// The statistics of every move of the root, from the most visited one.
+    pub move_statistics: Vec<MoveStatistics>,
+}
+
+impl SearchInfo {
+    fn new(tree: &Tree, playouts: u32, elapsed: Duration) -> Self {
+        Self {
+            elapsed,
+            playouts,
+            nodes_per_second: playouts as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            root_visits: tree.root_statistics().visits,
+            node_count: tree.node_count(),
+            depth: tree.depth(),
+            principal_variation: tree.principal_variation(),
+            move_statistics: tree.move_statistics(),
+        }
+    }
+
+    fn trace(&self) {
+        let principal_variation = self
+            .principal_variation
+            .iter()
+            .map(ChessMove::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        tracing::info!(
+            elapsed = ?self.elapsed,
+            playouts = self.playouts,
+            nodes_per_second = self.nodes_per_second,
+            root_visits = self.root_visits,
+            node_count = self.node_count,
+            depth = self.depth,
+            principal_variation,
+            "search info",
+        );
+
+        for move_statistics in &self.move_statistics {
+            tracing::debug!(
+                chess_move = %move_statistics.chess_move,
+                visits = move_statistics.visits,
+                q_value = ?move_statistics.q_value,
+                prior = move_statistics.prior,
+                "move statistics",
+            );
+        }
+    }
+}
+
+/// How the tree is searched: the number of threads growing it, how the leaves they select are
+/// batched for evaluation, whether the tree keeps growing between searches, and the temperature
+/// the moves of searches are sampled with.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchParameters {
+    pub search_threads: usize,
+    pub batch_parameters: BatchParameters,
+    pub ponder: bool,
+    pub temperature_schedule: TemperatureSchedule,
+}
+
+// The state shared between the search threads and the thread handling commands
+struct SearchState {
+    is_stopped: AtomicBool,
+    is_searching: AtomicBool,
+    ponder: bool,
+    playouts: AtomicU32,
+    max_playouts: AtomicU32,
+    max_nodes: AtomicUsize,
+    // When the running or last search started
+    start: Mutex<Instant>,
+}
+
+impl SearchState {
+    fn new(ponder: bool) -> Self {
+        Self {
+            is_stopped: AtomicBool::new(false),
+            is_searching: AtomicBool::new(false),
+            ponder,
+            playouts: AtomicU32::new(0),
+            max_playouts: AtomicU32::new(u32::MAX),
+            max_nodes: AtomicUsize::new(usize::MAX),
+            start: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn start(&self, search_limits: &SearchLimits) {
+        *self.start.lock().expect("mutex is poisoned") = Instant::now();
+        self.playouts.store(0, Ordering::Relaxed);
+        self.max_playouts.store(
+            search_limits.max_playouts.unwrap_or(u32::MAX),
+            Ordering::Relaxed,
+        );
+        self.max_nodes.store(
+            search_limits.max_nodes.unwrap_or(usize::MAX),
+            Ordering::Relaxed,
+        );
+        self.is_searching.store(true, Ordering::Relaxed);
+    }
+
+    // Returns whether a search thread may run another playout. Playouts of a search are reserved
+    // up front, so that searches never run more playouts than their limit.
+    fn try_start_playout(&self, tree: &Tree) -> bool {
+        if !self.is_searching.load(Ordering::Relaxed) {
+            return self.ponder;
+        }
+
+        let max_playouts = self.max_playouts.load(Ordering::Relaxed);
+
+        tree.node_count() < self.max_nodes.load(Ordering::Relaxed)
+            && self
+                .playouts
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |playouts| {
+                    (playouts < max_playouts).then_some(playouts + 1)
+                })
+                .is_ok()
+    }
+}
+
+pub(crate) struct RunningSearch {
+    limits: SearchLimits,
+    start: Instant,
+    last_info: Instant,
+}
+
+impl RunningSearch {
+    pub(crate) fn new(limits: SearchLimits) -> Self {
+        Self {
+            limits,
+            start: Instant::now(),
+            last_info: Instant::now(),
+        }
+    }
+
+    // Returns the first limit of the search which has been reached, if any
+    fn reached_limit(&self, tree: &Tree, playouts: u32) -> Option<StopReason> {
+        let elapsed = self.start.elapsed();
+
+        if self
+            .limits
+            .max_nodes
+            .is_some_and(|max_nodes| tree.node_count() >= max_nodes)
+        {
+            Some(StopReason::MaxNodes)
+        } else if self
+            .limits
+            .max_playouts
+            .is_some_and(|max_playouts| playouts >= max_playouts)
+        {
+            Some(StopReason::MaxPlayouts)
+        } else if self
+            .limits
+            .max_time
+            .is_some_and(|max_time| elapsed >= max_time)
+        {
+            Some(StopReason::MaxTime)
+        } else if self.limits.stop_when_unassailable
+            && self.is_unassailable(&tree.root_statistics(), playouts, elapsed)
+        {
+            Some(StopReason::Unassailable)
+        } else {
+            None
+        }
+    }
+
+    // Returns whether the second most visited move would stay behind the most visited one, even if
+    // it got every playout left before the playout or time limit
+    pub(crate) fn is_unassailable(
+        &self,
+        root_statistics: &RootStatistics,
+        playouts: u32,
+        elapsed: Duration,
+    ) -> bool {
+        let remaining_playouts_by_count = self
+            .limits
+            .max_playouts
+            .map(|max_playouts| max_playouts.saturating_sub(playouts) as f64);
+
+        // The playouts left before the time limit are estimated from the speed of the search, once
+        // it can be measured
+        let remaining_playouts_by_time = match self.limits.max_time {
+            Some(_)
+                if elapsed < MIN_SPEED_MEASUREMENT_TIME
+                    || playouts < MIN_SPEED_MEASUREMENT_PLAYOUTS =>
+            {
+                return false;
+            }
+            Some(max_time) => Some(
+                playouts as f64 / elapsed.as_secs_f64()
+                    * max_time.saturating_sub(elapsed).as_secs_f64(),
+            ),
+            None => None,
+        };
+
+        let remaining_playouts = match (remaining_playouts_by_count, remaining_playouts_by_time) {
+            (Some(by_count), Some(by_time)) => by_count.min(by_time),
+            (Some(remaining_playouts), None) | (None, Some(remaining_playouts)) => {
+                remaining_playouts
+            }
+            (None, None) => return false,
+        };
+
+        root_statistics.best_move.is_some()
+            && (root_statistics.best_move_visits - root_statistics.second_best_move_visits) as f64
+                > remaining_playouts
+    }
+}
+
+/// A handle to running search threads, through which commands are sent and search results are
+/// received, and the tree being searched is inspected. Search infos are sent every second while a
+/// search is running, and once it stops, and are dropped while too many wait to be received.
+pub struct SearchHandle {
+    pub command_sender: Sender<SearchCommand>,
+    pub result_receiver: Receiver<SearchResult>,
+    pub info_receiver: Receiver<SearchInfo>,
+    tree: Arc<RwLock<Tree>>,
+    search_state: Arc<SearchState>,
+}
+
+impl SearchHandle {
+    /// Returns the statistics of the root of the tree, as the search currently stands.
+    pub fn root_statistics(&self) -> RootStatistics {
+        self.tree
+            .read()
+            .expect("rwlock is poisoned")
+            .root_statistics()
+    }
+
+    pub fn root_board(&self) -> Board {
+        *self.tree.read().expect("rwlock is poisoned").root_board()
+    }
+
+    /// Returns a snapshot of the running search, or of the tree since the last search if none is
+    /// running.
+    pub fn search_info(&self) -> SearchInfo {
+        let elapsed = self
+            .search_state
+            .start
+            .lock()
+            .expect("mutex is poisoned")
+            .elapsed();
+
+        SearchInfo::new(
+            &self.tree.read().expect("rwlock is poisoned"),
+            self.search_state.playouts.load(Ordering::Relaxed),
+            elapsed,
+        )
+    }
+}
+
+// Sends a search info, unless too many are already waiting to be received, and traces it
+fn send_info(info_sender: &SyncSender<SearchInfo>, search_info: SearchInfo) {
+    search_info.trace();
+
+    // Infos are only sent for analysis, so dropping them when nobody receives them is fine
+    let _ = info_sender.try_send(search_info);
 }
 
-pub fn start_search_thread<B: Backend>(
-    mut tree: Tree,
-    network: Pisa<B>,
-    exploration_rate: f32,
-) -> (Sender<SearchCommand>, Receiver<ChessMove>) {
+/// Starts the passed number of search threads, which grow the tree concurrently, along with a
+/// thread evaluating their leaves in batches, and a thread handling commands and enforcing the
+/// limits of searches. Commands advance the tree once the search threads have finished their
+/// current playouts, and the search threads stop once the command sender is dropped.
+pub fn start_search_thread(
+    tree: Tree,
+    evaluator: impl Evaluator,
+    search_parameters: SearchParameters,
+) -> SearchHandle {
     let (command_sender, command_receiver) = mpsc::channel();
-    let (best_move_sender, best_move_receiver) = mpsc::channel();
+    let (result_sender, result_receiver) = mpsc::channel();
+    let (info_sender, info_receiver) = mpsc::sync_channel(INFO_CHANNEL_CAPACITY);
 
-    thread::spawn(move || loop {
-        match command_receiver.try_recv() {
-            Err(TryRecvError::Empty) => {
-                tracing::trace!("growing tree");
+    let tree = Arc::new(RwLock::new(tree));
+    let evaluation_queue = EvaluationQueue::start(evaluator, search_parameters.batch_parameters);
+    let search_state = Arc::new(SearchState::new(search_parameters.ponder));
 
-                tree.grow(&network, exploration_rate);
+    for _ in 0..search_parameters.search_threads.max(1) {
+        let tree = Arc::clone(&tree);
+        let evaluation_queue = evaluation_queue.clone();
+        let search_state = Arc::clone(&search_state);
+
+        thread::spawn(move || {
+            while !search_state.is_stopped.load(Ordering::Relaxed) {
+                let tree = tree.read().expect("rwlock is poisoned");
+
+                // The tree stays full until the root is advanced
+                let grew = search_state.try_start_playout(&tree) && tree.grow(&evaluation_queue);
+
+                drop(tree);
+
+                if !grew {
+                    thread::sleep(IDLE_BACKOFF);
+                }
             }
-            Ok(command) => match command {
-                SearchCommand::SendAndPlayBestMove => {
-                    let best_move = tree.best_move().unwrap();
+        });
+    }
 
-                    tracing::info!(%best_move, "found best move");
+    let search_handle = SearchHandle {
+        command_sender,
+        result_receiver,
+        info_receiver,
+        tree: Arc::clone(&tree),
+        search_state: Arc::clone(&search_state),
+    };
 
-                    if best_move_sender.send(best_move).is_err() {
-                        return;
-                    }
+    thread::spawn(move || {
+        let mut running_search: Option<RunningSearch> = None;
 
-                    tracing::info!(%best_move, "growing tree");
+        loop {
+            // Limits are checked between commands while a search is running
+            let command = match running_search {
+                Some(_) => match command_receiver.recv_timeout(LIMIT_CHECK_INTERVAL) {
+                    Ok(command) => Some(command),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+                None => match command_receiver.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => break,
+                },
+            };
 
-                    tree.try_advance(best_move).unwrap();
+            let mut is_stopped = false;
+
+            match command {
+                Some(SearchCommand::Search(search_limits)) => {
+                    tracing::info!(?search_limits, "started search");
+
+                    search_state.start(&search_limits);
+                    running_search = Some(RunningSearch::new(search_limits));
                 }
-                SearchCommand::PlayedMove(chess_move) => {
+                Some(SearchCommand::Stop) => is_stopped = true,
+                Some(SearchCommand::PlayedMove(chess_move, advance_result_sender)) => {
                     tracing::info!(%chess_move, "received opponent move");
 
-                    tree.try_advance(chess_move)
-                        .expect("opponent move is illegal or invalid");
+                    let mut tree = tree.write().expect("rwlock is poisoned");
+                    let chess_move = tree.root_board().castle_from_king_target(chess_move);
+
+                    // The root is left unexpanded when the opponent moves before a playout ran
+                    // since the last move
+                    let advance_result = match tree.try_advance(chess_move) {
+                        Err(AdvanceTreeError::NotExpandedError) => {
+                            tree.grow(&evaluation_queue);
+                            tree.try_advance(chess_move)
+                        }
+                        advance_result => advance_result,
+                    };
+
+                    if let Err(error) = &advance_result {
+                        tracing::warn!(%chess_move, %error, "failed to play opponent move");
+                    }
+
+                    // The sender no longer waiting for the result is not an error
+                    let _ = advance_result_sender.send(advance_result);
                 }
-            },
-            Err(TryRecvError::Disconnected) => return,
+                None => {}
+            }
+
+            let Some(search) = &mut running_search else {
+                continue;
+            };
+
+            let playouts = search_state.playouts.load(Ordering::Relaxed);
+
+            if search.last_info.elapsed() >= INFO_INTERVAL {
+                search.last_info = Instant::now();
+
+                send_info(
+                    &info_sender,
+                    SearchInfo::new(
+                        &tree.read().expect("rwlock is poisoned"),
+                        playouts,
+                        search.start.elapsed(),
+                    ),
+                );
+            }
+
+            let stop_reason = if is_stopped {
+                StopReason::Stopped
+            } else if let Some(stop_reason) =
+                search.reached_limit(&tree.read().expect("rwlock is poisoned"), playouts)
+            {
+                stop_reason
+            } else {
+                continue;
+            };
+
+            let start = search.start;
+
+            search_state.is_searching.store(false, Ordering::Relaxed);
+            running_search = None;
+
+            // Waiting for the write lock lets the playouts in flight finish first
+            let mut tree = tree.write().expect("rwlock is poisoned");
+
+            // A search stopped before its first playout still needs the root expanded to pick a
+            // move from
+            if tree.root_statistics().visits == 0 {
+                tree.grow(&evaluation_queue);
+            }
+
+            let temperature = search_parameters
+                .temperature_schedule
+                .temperature(tree.root_board().ply());
+            let best_move = tree.sample_move(temperature, &mut rand::thread_rng());
+
+            send_info(
+                &info_sender,
+                SearchInfo::new(
+                    &tree,
+                    search_state.playouts.load(Ordering::Relaxed),
+                    start.elapsed(),
+                ),
+            );
+
+            tracing::info!(?best_move, ?stop_reason, "found best move");
+
+            // Castles are sent with the king's target square, as standard chess protocols expect
+            let search_result = SearchResult {
+                best_move: best_move
+                    .map(|best_move| tree.root_board().castle_to_king_target(best_move)),
+                stop_reason,
+            };
+
+            if result_sender.send(search_result).is_err() {
+                break;
+            }
+
+            if let Some(best_move) = best_move {
+                tree.try_advance(best_move)
+                    .expect("best move is a move of the root");
+            }
         }
+
+        search_state.is_stopped.store(true, Ordering::Relaxed);
     });
 
-    (command_sender, best_move_receiver)
+    search_handle
 }
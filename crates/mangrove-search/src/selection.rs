@@ -0,0 +1,152 @@
+//! Policies choosing which child of a node to search next, balancing how well children scored
+//! against their priors and how rarely they were visited.
+
+/// The statistics of a child of a node, for the player to move in the node. Selections through
+/// the child which are still waiting to be backpropagated count as losses.
+#[derive(Debug, Clone, Copy)]
+pub struct ChildStatistics {
+    pub visits: u32,
+    pub value_sum: f32,
+    /// The sum of the squares of the values backpropagated through the child.
+    pub value_square_sum: f32,
+    pub prior: f32,
+}
+
+impl ChildStatistics {
+    /// The mean value of the child, or `None` if it wasn't visited yet.
+    pub fn q_value(&self) -> Option<f32> {
+        (self.visits > 0).then(|| self.value_sum / self.visits as f32)
+    }
+}
+
+/// The statistics of the node whose children are being chosen between.
+#[derive(Debug, Clone, Copy)]
+pub struct ParentStatistics {
+    /// The visits of the node, counting the one which expanded it.
+    pub visits: u32,
+    /// The value the node was evaluated with, for its player to move.
+    pub value: f32,
+}
+
+/// A policy scoring the children of a node, the best scoring of which is searched next.
+pub trait SelectionPolicy: Send + Sync {
+    fn score(&self, parent: &ParentStatistics, child: &ChildStatistics) -> f32;
+}
+
+impl<T: SelectionPolicy + ?Sized> SelectionPolicy for Box<T> {
+    fn score(&self, parent: &ParentStatistics, child: &ChildStatistics) -> f32 {
+        (**self).score(parent, child)
+    }
+}
+
+/// The value children which weren't visited yet are scored with.
+#[derive(Debug, Clone, Copy)]
+pub enum FirstPlayUrgency {
+    Absolute(f32),
+    /// The value of the parent, reduced by the passed amount.
+    Reduction(f32),
+}
+
+impl FirstPlayUrgency {
+    fn q_value(self, parent: &ParentStatistics, child: &ChildStatistics) -> f32 {
+        child.q_value().unwrap_or(match self {
+            Self::Absolute(value) => value,
+            Self::Reduction(reduction) => parent.value - reduction,
+        })
+    }
+}
+
+// The exploration term of PUCT, without the exploration rate
+fn exploration(parent: &ParentStatistics, child: &ChildStatistics) -> f32 {
+    child.prior * (parent.visits as f32).sqrt() / (1 + child.visits) as f32
+}
+
+/// AlphaZero's PUCT, which scores children with `Q + c * P * sqrt(N) / (1 + n)`, where `N` and `n`
+/// are the visits of the parent and of the child.
+#[derive(Debug, Clone, Copy)]
+pub struct Puct {
+    pub exploration_rate: f32,
+    pub first_play_urgency: FirstPlayUrgency,
+}
+
+impl Default for Puct {
+    fn default() -> Self {
+        Self {
+            exploration_rate: 1.75,
+            first_play_urgency: FirstPlayUrgency::Reduction(0.3),
+        }
+    }
+}
+
+impl SelectionPolicy for Puct {
+    fn score(&self, parent: &ParentStatistics, child: &ChildStatistics) -> f32 {
+        self.first_play_urgency.q_value(parent, child)
+            + self.exploration_rate * exploration(parent, child)
+    }
+}
+
+/// Lc0's PUCT, whose exploration rate grows with the visits of the parent, as
+/// `c + factor * ln((N + base) / base)`, so wide trees keep exploring.
+#[derive(Debug, Clone, Copy)]
+pub struct LogPuct {
+    pub exploration_rate: f32,
+    pub exploration_base: f32,
+    pub exploration_factor: f32,
+    pub first_play_urgency: FirstPlayUrgency,
+}
+
+impl Default for LogPuct {
+    fn default() -> Self {
+        Self {
+            exploration_rate: 1.75,
+            exploration_base: 38739.0,
+            exploration_factor: 3.9,
+            first_play_urgency: FirstPlayUrgency::Reduction(0.3),
+        }
+    }
+}
+
+impl SelectionPolicy for LogPuct {
+    fn score(&self, parent: &ParentStatistics, child: &ChildStatistics) -> f32 {
+        let exploration_rate = self.exploration_rate
+            + self.exploration_factor
+                * ((parent.visits as f32 + self.exploration_base) / self.exploration_base).ln();
+
+        self.first_play_urgency.q_value(parent, child)
+            + exploration_rate * exploration(parent, child)
+    }
+}
+
+/// PUCT whose exploration term is scaled by the standard deviation of the values of each child,
+/// relative to `prior_variance`, so children whose values disagree are explored more. The variance
+/// of rarely visited children is pulled towards `prior_variance`, which unvisited children get.
+#[derive(Debug, Clone, Copy)]
+pub struct VariancePuct {
+    pub exploration_rate: f32,
+    pub prior_variance: f32,
+    pub first_play_urgency: FirstPlayUrgency,
+}
+
+impl Default for VariancePuct {
+    fn default() -> Self {
+        Self {
+            exploration_rate: 1.75,
+            prior_variance: 0.5,
+            first_play_urgency: FirstPlayUrgency::Reduction(0.3),
+        }
+    }
+}
+
+impl SelectionPolicy for VariancePuct {
+    fn score(&self, parent: &ParentStatistics, child: &ChildStatistics) -> f32 {
+        // The prior variance counts as one more value
+        let squared_deviation_sum =
+            (child.value_square_sum - child.value_sum * child.q_value().unwrap_or(0.0)).max(0.0);
+        let variance = (self.prior_variance + squared_deviation_sum) / (1 + child.visits) as f32;
+
+        self.first_play_urgency.q_value(parent, child)
+            + self.exploration_rate
+                * (variance / self.prior_variance).sqrt()
+                * exploration(parent, child)
+    }
+}
@@ -1,41 +1,221 @@
 use std::{
-    mem::MaybeUninit,
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    cmp::Reverse,
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    mem,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
 };
 
-use burn::tensor::backend::Backend;
-use mangrove_core::{board::Board, mg, repr::ChessMove};
-use mangrove_pisa::Pisa;
+use mangrove_core::{
+    board::Board,
+    game::{self, DrawReason, Outcome},
+    mg,
+    repr::ChessMove,
+};
+use mangrove_pisa::HistoryBoard;
+use rand::Rng;
 use ringbuffer::{AllocRingBuffer, RingBuffer};
 
-use crate::puct;
+use crate::{
+    evaluation::LeafEvaluator,
+    exploration::{self, DirichletNoise},
+    selection::{ChildStatistics, ParentStatistics, Puct, SelectionPolicy},
+};
 
 type TreeNodeIndex = usize;
 
+/// The value counted against a node for each selection through it which has not yet been
+/// backpropagated, so concurrent searchers are steered away from the paths of one another.
+const VIRTUAL_LOSS: f32 = 1.0;
+
 #[derive(Clone, Copy)]
 pub(crate) struct TreeNodeMetadata {
+    // The sum of the values backpropagated through the node, for the player who made its move
     pub(crate) value_sum: f32,
+    pub(crate) value_square_sum: f32,
     pub(crate) visits: u32,
+    // The number of selections through the node which are still waiting to be backpropagated
+    pub(crate) virtual_losses: u32,
     pub(crate) probability: f32,
-    chess_move: ChessMove,
+    // The move leading to the node, which only the root of a new tree lacks
+    chess_move: Option<ChessMove>,
+}
+
+impl TreeNodeMetadata {
+    fn new(probability: f32, chess_move: Option<ChessMove>) -> Self {
+        Self {
+            value_sum: 0.0,
+            value_square_sum: 0.0,
+            visits: 0,
+            virtual_losses: 0,
+            probability,
+            chess_move,
+        }
+    }
+
+    /// The move leading to the node, which every child has.
+    pub(crate) fn chess_move(&self) -> ChessMove {
+        self.chess_move.expect("node is the root of a new tree")
+    }
+
+    /// The visits of the node, counting pending selections as visits.
+    pub(crate) fn effective_visits(&self) -> u32 {
+        self.visits + self.virtual_losses
+    }
+
+    /// The value sum of the node, counting pending selections as losses.
+    pub(crate) fn effective_value_sum(&self) -> f32 {
+        self.value_sum - self.virtual_losses as f32 * VIRTUAL_LOSS
+    }
+
+    /// The statistics the node is selected with, counting pending selections as losses.
+    fn child_statistics(&self) -> ChildStatistics {
+        ChildStatistics {
+            visits: self.effective_visits(),
+            value_sum: self.effective_value_sum(),
+            value_square_sum: self.value_square_sum
+                + self.virtual_losses as f32 * VIRTUAL_LOSS * VIRTUAL_LOSS,
+            prior: self.probability,
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct TreeNode {
-    metadata: MaybeUninit<TreeNodeMetadata>,
+    metadata: TreeNodeMetadata,
     children_info: Option<(TreeNodeIndex, TreeNodeIndex)>,
+    // The result of the node for its player to move, if the game is over in the node, or the
+    // results of its children prove it
+    proven_result: Option<ProvenResult>,
+    // The value the node was evaluated with when expanded, for its player to move
+    value: f32,
 }
 
 impl TreeNode {
     fn is_expanded(&self) -> bool {
         self.children_info.is_some()
     }
+
+    fn is_proven(&self) -> bool {
+        self.proven_result.is_some()
+    }
+}
+
+/// The result of a node for its player to move, under perfect play from both players.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProvenResult {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl ProvenResult {
+    fn value(self) -> f32 {
+        match self {
+            Self::Win => 1.0,
+            Self::Loss => -1.0,
+            Self::Draw => 0.0,
+        }
+    }
+}
+
+/// A path selected from the root of a tree to one of its leaves.
+pub(crate) struct Selection {
+    pub(crate) path: Box<[TreeNodeIndex]>,
+    // The last boards of the game up to the leaf, as many as the evaluator considers, with their
+    // repetitions over the whole game
+    pub(crate) boards: Box<[HistoryBoard]>,
+    // The result of the leaf for its player to move, if it is proven
+    pub(crate) proven_result: Option<ProvenResult>,
+    // Whether the game is drawn in the leaf by repetition or the move-count rules
+    pub(crate) is_drawn_by_history: bool,
+    pub(crate) transposition_key: u64,
+}
+
+/// Returns the key of a board in the transposition table, given the hashes of the game up to it,
+/// as [`game::outcome`] expects. Boards reached through different moves share a key, unless the
+/// board occurred a different number of times in their games, as one more repetition draws the
+/// game from it. Other repetitions along the way are left to the search, which scores such draws
+/// per playout, as [`Tree::grow`] describes.
+pub(crate) fn transposition_key(board: &Board, hashes: &[u64]) -> u64 {
+    let earlier_occurrences = game::repetitions(board, hashes).saturating_sub(1) as u64;
+
+    board.hash ^ earlier_occurrences.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+/// Returns the result of a board for its player to move, if the game is over in it. The hashes
+/// are those of the game up to the board, as [`game::outcome`] expects.
+pub(crate) fn terminal_result(board: &Board, hashes: &[u64]) -> Option<ProvenResult> {
+    Some(match game::outcome(board, hashes)? {
+        Outcome::Draw(_) => ProvenResult::Draw,
+        outcome if outcome == Outcome::Win(board.playing_color) => ProvenResult::Win,
+        Outcome::Win(_) => ProvenResult::Loss,
+    })
+}
+
+/// Returns whether the game is drawn in a board by repetition or the move-count rules, given the
+/// hashes of the game up to it, as [`game::outcome`] expects. These draws depend on the path to the
+/// board rather than the board itself, so unlike other results they never prove a node, as the
+/// node may be reached through other paths, where the game goes on.
+pub(crate) fn is_drawn_by_history(board: &Board, hashes: &[u64]) -> bool {
+    // Generating the moves of the board to rule out checkmate is only worth it once a draw is
+    // possible
+    (board.min_ply_clock >= game::FIFTY_MOVE_RULE_PLIES || game::repetitions(board, hashes) >= 3)
+        && matches!(
+            game::outcome(board, hashes),
+            Some(Outcome::Draw(
+                DrawReason::ThreefoldRepetition
+                    | DrawReason::FivefoldRepetition
+                    | DrawReason::FiftyMoveRule
+                    | DrawReason::SeventyFiveMoveRule
+            ))
+        )
+}
+
+/// The visits of the root of a tree, and of its two most visited children.
+#[derive(Debug, Clone, Copy)]
+pub struct RootStatistics {
+    pub visits: u32,
+    pub best_move: Option<ChessMove>,
+    pub best_move_visits: u32,
+    pub second_best_move_visits: u32,
+}
+
+/// The statistics of a move of the root of a tree.
+#[derive(Debug, Clone, Copy)]
+pub struct MoveStatistics {
+    pub chess_move: ChessMove,
+    pub visits: u32,
+    /// The mean value backpropagated through the move, for the player to move in the root, or
+    /// `None` if the move wasn't visited yet.
+    pub q_value: Option<f32>,
+    /// The probability of the move given by the network.
+    pub prior: f32,
 }
 
 pub struct Tree {
     nodes: boxcar::Vec<RwLock<TreeNode>>,
+    // Held while pushing the children of a node, as they must be contiguous
+    expansion_lock: Mutex<()>,
     root_index: TreeNodeIndex,
     root_board: Board,
+    // The boards of the game before the root board, in order, which start the histories of the
+    // leaves, so leaves are evaluated with the same histories whichever move the root is at
+    previous_boards: Vec<HistoryBoard>,
+    // The hashes of every position of the game up to the root board, including its own
+    root_hashes: Vec<u64>,
+    // Expanded nodes, keyed by the transposition keys of their boards. Nodes reaching a board
+    // which was already expanded share the children of the first node to expand it.
+    transpositions: Option<Mutex<HashMap<u64, TreeNodeIndex>>>,
+    evaluations: AtomicU64,
+    // The length of the longest path selected from the root
+    depth: AtomicUsize,
+    // The memory the tree may use before it stops growing, in bytes
+    memory_limit: Option<usize>,
+    root_noise: Option<DirichletNoise>,
+    selection_policy: Box<dyn SelectionPolicy>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -47,7 +227,7 @@ pub enum AdvanceTreeError {
 }
 
 impl Tree {
-    fn get(&self, index: TreeNodeIndex) -> RwLockReadGuard<TreeNode> {
+    pub(crate) fn get(&self, index: TreeNodeIndex) -> RwLockReadGuard<TreeNode> {
         self.nodes[index].read().expect("rwlock is poisoned")
     }
 
@@ -59,61 +239,374 @@ impl Tree {
         Self {
             nodes: boxcar::vec![RwLock::new(TreeNode {
                 children_info: None,
-                metadata: MaybeUninit::uninit()
+                proven_result: None,
+                value: 0.0,
+                metadata: TreeNodeMetadata::new(1.0, None),
             })],
+            expansion_lock: Mutex::new(()),
             root_index: 0,
             root_board: board,
+            previous_boards: vec![],
+            root_hashes: vec![board.hash],
+            transpositions: Some(Mutex::new(HashMap::new())),
+            evaluations: AtomicU64::new(0),
+            depth: AtomicUsize::new(0),
+            memory_limit: None,
+            root_noise: None,
+            selection_policy: Box::new(Puct::default()),
         }
     }
 
+    /// Sets roughly how much memory the tree may use, in bytes. Once the limit is reached, the tree
+    /// stops growing until the root is advanced and the nodes outside of the new root's subtree are
+    /// reclaimed. There is no limit by default.
+    pub fn with_memory_limit(mut self, memory_limit: usize) -> Self {
+        self.memory_limit = Some(memory_limit);
+        self
+    }
+
+    /// Returns the number of nodes in the tree.
+    pub fn node_count(&self) -> usize {
+        self.nodes.count()
+    }
+
+    /// Returns roughly how much memory the nodes of the tree and its transposition table use, in
+    /// bytes.
+    pub fn memory_usage(&self) -> usize {
+        let node_size = mem::size_of::<RwLock<TreeNode>>()
+            + self
+                .transpositions
+                .as_ref()
+                .map_or(0, |_| mem::size_of::<(u64, TreeNodeIndex)>());
+
+        self.node_count() * node_size
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.memory_limit
+            .is_some_and(|memory_limit| self.memory_usage() >= memory_limit)
+    }
+
+    /// Sets the policy choosing which child of a node to search next. The default [`Puct`] is used
+    /// otherwise.
+    pub fn with_selection_policy(
+        mut self,
+        selection_policy: impl SelectionPolicy + 'static,
+    ) -> Self {
+        self.selection_policy = Box::new(selection_policy);
+        self
+    }
+
+    /// Sets the Dirichlet noise mixed into the priors of the moves of the root, whenever the root is
+    /// expanded or advanced. There is no noise by default.
+    pub fn with_root_noise(mut self, root_noise: DirichletNoise) -> Self {
+        self.root_noise = Some(root_noise);
+        self
+    }
+
+    // Mixes the root noise into the priors of the moves of the root, if it is expanded. The
+    // children of the root are never shared through transpositions while there is noise, so no
+    // other node sees it.
+    fn add_root_noise(&self) {
+        let Some(root_noise) = self.root_noise else {
+            return;
+        };
+
+        let Some((start, end)) = self.root().children_info else {
+            return;
+        };
+
+        let mut priors = (start..end)
+            .map(|child_index| self.get(child_index).metadata.probability)
+            .collect::<Vec<_>>();
+
+        root_noise.apply(&mut priors, &mut rand::thread_rng());
+
+        for (child_index, prior) in (start..end).zip(priors) {
+            self.get_mut(child_index).metadata.probability = prior;
+        }
+    }
+
+    /// Sets whether transpositions share the evaluations and statistics of their children. They
+    /// do by default.
+    pub fn with_transpositions(mut self, transpositions: bool) -> Self {
+        self.transpositions = transpositions.then(|| Mutex::new(HashMap::new()));
+        self
+    }
+
+    /// Returns the number of boards evaluated by the network while growing the tree.
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations.load(Ordering::Relaxed)
+    }
+
+    /// Returns the length of the longest path selected from the root, in plies.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
     pub fn root(&self) -> RwLockReadGuard<TreeNode> {
         self.get(self.root_index)
     }
 
-    fn get_children_metadata<'a>(
+    pub fn root_board(&self) -> &Board {
+        &self.root_board
+    }
+
+    /// Returns the result of the root board for its player to move, if the search has proven it.
+    pub fn root_result(&self) -> Option<ProvenResult> {
+        self.root().proven_result
+    }
+
+    pub(crate) fn get_children_metadata<'a>(
         &'a self,
         tree_node: &'a TreeNode,
     ) -> Option<impl Iterator<Item = (TreeNodeIndex, TreeNodeMetadata)> + 'a> {
         let (start, end) = tree_node.children_info?;
 
-        Some((start..end).map(|child_index| {
-            (child_index, unsafe {
-                self.get(child_index).metadata.assume_init()
-            })
-        }))
+        Some((start..end).map(|child_index| (child_index, self.get(child_index).metadata)))
     }
 
     pub fn try_advance(&mut self, chess_move: ChessMove) -> Result<(), AdvanceTreeError> {
         let next_root_index = self
             .get_children_metadata(&self.root())
             .ok_or(AdvanceTreeError::NotExpandedError)?
-            .find(|(_, child_metadata)| child_metadata.chess_move == chess_move)
+            .find(|(_, child_metadata)| child_metadata.chess_move() == chess_move)
             .ok_or(AdvanceTreeError::IllegalMove)?
             .0;
 
-        self.root_index = next_root_index;
-
+        self.previous_boards
+            .push(HistoryBoard::new(self.root_board, &self.root_hashes));
         self.root_board.make_move(chess_move).unwrap();
+        self.root_hashes.push(self.root_board.hash);
+
+        self.compact(next_root_index);
+        self.add_root_noise();
 
         Ok(())
     }
 
+    // Copies the subtree of the passed node into a fresh arena, with the node as its root, so the
+    // memory of every node outside of the subtree is reclaimed. Children shared by transpositions
+    // are copied once, and stay shared.
+    fn compact(&mut self, root_index: TreeNodeIndex) {
+        let old_nodes = mem::replace(&mut self.nodes, boxcar::Vec::new());
+        let old_node = |index: TreeNodeIndex| *old_nodes[index].read().expect("rwlock is poisoned");
+
+        // The new indices of copied nodes, and of the first nodes of copied children
+        let mut new_indices = HashMap::from([(root_index, 0)]);
+        let mut new_children_starts = HashMap::new();
+
+        self.nodes.push(RwLock::new(old_node(root_index)));
+        let mut uncopied_children = VecDeque::from([0]);
+
+        while let Some(node_index) = uncopied_children.pop_front() {
+            let mut node = self.get_mut(node_index);
+
+            let Some((start, end)) = node.children_info else {
+                continue;
+            };
+
+            let new_start = match new_children_starts.entry(start) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    let new_start = self.nodes.count();
+
+                    for old_child_index in start..end {
+                        let new_child_index =
+                            self.nodes.push(RwLock::new(old_node(old_child_index)));
+
+                        new_indices.insert(old_child_index, new_child_index);
+                        uncopied_children.push_back(new_child_index);
+                    }
+
+                    *entry.insert(new_start)
+                }
+            };
+
+            node.children_info = Some((new_start, new_start + end - start));
+
+            // Other nodes sharing the children of the root get copies of their own, as only the
+            // root gets noise
+            if node_index == 0 && self.root_noise.is_some() {
+                new_children_starts.remove(&start);
+            }
+        }
+
+        if let Some(transpositions) = &mut self.transpositions {
+            let transpositions = transpositions.get_mut().expect("mutex is poisoned");
+
+            transpositions.retain(|_, node_index| match new_indices.get(node_index) {
+                Some(&new_index) => {
+                    *node_index = new_index;
+                    true
+                }
+                None => false,
+            });
+        }
+
+        self.root_index = 0;
+
+        // Paths from the new root are a ply shorter than those through it from the old one
+        let depth = self.depth.get_mut();
+        *depth = depth.saturating_sub(1);
+
+        tracing::debug!(nodes = self.nodes.count(), "compacted tree");
+    }
+
+    /// Returns the visits of the root and of its two most visited children.
+    pub fn root_statistics(&self) -> RootStatistics {
+        let root = self.root();
+
+        let mut statistics = RootStatistics {
+            visits: root.metadata.visits,
+            best_move: None,
+            best_move_visits: 0,
+            second_best_move_visits: 0,
+        };
+
+        let Some(children) = self.get_children_metadata(&root) else {
+            return statistics;
+        };
+
+        for (_, child_metadata) in children {
+            if statistics.best_move.is_none() || child_metadata.visits > statistics.best_move_visits
+            {
+                statistics.second_best_move_visits = statistics.best_move_visits;
+                statistics.best_move_visits = child_metadata.visits;
+                statistics.best_move = Some(child_metadata.chess_move());
+            } else if child_metadata.visits > statistics.second_best_move_visits {
+                statistics.second_best_move_visits = child_metadata.visits;
+            }
+        }
+
+        statistics
+    }
+
+    /// Returns the most visited move of the root, unless the results of some moves are proven:
+    /// moves proven to win are always preferred, and moves proven to lose are only played when no
+    /// other move is left.
     pub fn best_move(&self) -> Option<ChessMove> {
-        self.get_children_metadata(&self.root())
-            .and_then(|children| {
-                Some(
-                    children
-                        .max_by_key(|(_, child_metadata)| child_metadata.visits)?
-                        .1
-                        .chess_move,
-                )
+        let best_child_index = self.best_child(&self.root())?;
+
+        Some(self.get(best_child_index).metadata.chess_move())
+    }
+
+    /// Samples a move of the root from the visits of every move, with the passed temperature, as
+    /// [`exploration::TemperatureSchedule`] describes. Moves proven to win are still always played,
+    /// and moves proven to lose are only played when no other move was visited.
+    pub fn sample_move(&self, temperature: f32, rng: &mut impl Rng) -> Option<ChessMove> {
+        let best_move = self.best_move();
+        let (start, end) = self.root().children_info?;
+
+        if temperature <= 0.0 {
+            return best_move;
+        }
+
+        let children = (start..end)
+            .map(|child_index| *self.get(child_index))
+            .collect::<Vec<_>>();
+
+        // Children hold results for the opponent
+        if children
+            .iter()
+            .any(|child| child.proven_result == Some(ProvenResult::Loss))
+        {
+            return best_move;
+        }
+
+        let visits = children
+            .iter()
+            .map(|child| match child.proven_result {
+                Some(ProvenResult::Win) => 0,
+                _ => child.metadata.visits,
+            })
+            .collect::<Vec<_>>();
+
+        match exploration::sample_visits(&visits, temperature, rng) {
+            Some(index) => Some(children[index].metadata.chess_move()),
+            None => best_move,
+        }
+    }
+
+    // Returns the child of a node which is best for its player to move, as `Tree::best_move`
+    // describes
+    fn best_child(&self, tree_node: &TreeNode) -> Option<TreeNodeIndex> {
+        let (start, end) = tree_node.children_info?;
+
+        (start..end).max_by_key(|&child_index| {
+            let child = *self.get(child_index);
+
+            // Children hold results for the opponent
+            let proven_rank = match child.proven_result {
+                Some(ProvenResult::Loss) => 2,
+                Some(ProvenResult::Draw) | None => 1,
+                Some(ProvenResult::Win) => 0,
+            };
+
+            (proven_rank, child.metadata.visits)
+        })
+    }
+
+    /// Returns the moves expected to be played from the root, following the best visited move of
+    /// each node.
+    pub fn principal_variation(&self) -> Vec<ChessMove> {
+        let mut principal_variation = vec![];
+        let mut node = *self.root();
+
+        while let Some(child_index) = self.best_child(&node) {
+            node = *self.get(child_index);
+
+            if node.metadata.visits == 0 {
+                break;
+            }
+
+            principal_variation.push(node.metadata.chess_move());
+        }
+
+        principal_variation
+    }
+
+    /// Returns the statistics of every move of the root, from the most visited one.
+    pub fn move_statistics(&self) -> Vec<MoveStatistics> {
+        let root = self.root();
+
+        let Some(children) = self.get_children_metadata(&root) else {
+            return vec![];
+        };
+
+        let mut move_statistics = children
+            .map(|(_, child_metadata)| MoveStatistics {
+                chess_move: child_metadata.chess_move(),
+                visits: child_metadata.visits,
+                q_value: (child_metadata.visits > 0)
+                    .then(|| child_metadata.value_sum / child_metadata.visits as f32),
+                prior: child_metadata.probability,
             })
+            .collect::<Vec<_>>();
+
+        move_statistics.sort_by_key(|move_statistics| Reverse(move_statistics.visits));
+        move_statistics
     }
 
-    fn select_child(&self, tree_node: &TreeNode, exploration_rate: f32) -> Option<TreeNodeIndex> {
-        self.get_children_metadata(tree_node)?
+    fn select_child(&self, tree_node: &TreeNode) -> Option<TreeNodeIndex> {
+        let children = self.get_children_metadata(tree_node)?.collect::<Vec<_>>();
+
+        let parent = ParentStatistics {
+            visits: 1 + children
+                .iter()
+                .map(|(_, child_metadata)| child_metadata.effective_visits())
+                .sum::<u32>(),
+            value: tree_node.value,
+        };
+
+        children
+            .into_iter()
             .map(|(child_index, child_metadata)| {
-                (child_index, puct::puct(&child_metadata, exploration_rate))
+                let score = self
+                    .selection_policy
+                    .score(&parent, &child_metadata.child_statistics());
+
+                (child_index, score)
             })
             .max_by(|(_, a), (_, b)| a.total_cmp(b))
             .map(|(child_index, _)| child_index)
@@ -122,15 +615,21 @@ impl Tree {
     pub(crate) fn expand(
         &self,
         node_index: TreeNodeIndex,
+        value: f32,
         move_probabilities: &[(f32, ChessMove)],
     ) {
-        let next_node_index = self.nodes.count();
         let mut node_to_expand = self.get_mut(node_index);
 
+        // Another thread may have expanded the node while this one was evaluating it
         if node_to_expand.is_expanded() {
             return;
         }
 
+        node_to_expand.value = value;
+
+        let expansion_guard = self.expansion_lock.lock().expect("mutex is poisoned");
+        let next_node_index = self.nodes.count();
+
         node_to_expand.children_info =
             Some((next_node_index, move_probabilities.len() + next_node_index));
 
@@ -139,81 +638,262 @@ impl Tree {
         for child in move_probabilities.iter().map(|&(probability, chess_move)| {
             RwLock::new(TreeNode {
                 children_info: None,
-                metadata: MaybeUninit::new(TreeNodeMetadata {
-                    value_sum: 0.0,
-                    visits: 0,
-                    probability,
-                    chess_move,
-                }),
+                proven_result: None,
+                value: 0.0,
+                metadata: TreeNodeMetadata::new(probability, Some(chess_move)),
             })
         }) {
             self.nodes.push(child);
         }
+
+        drop(expansion_guard);
+        drop(node_to_expand);
+
+        if node_index == self.root_index {
+            self.add_root_noise();
+        }
     }
 
-    pub(crate) fn select(
-        &self,
-        exploration_rate: f32,
-        move_history: usize,
-    ) -> (Box<[TreeNodeIndex]>, Box<[Board]>) {
+    pub(crate) fn add_virtual_loss(&self, node_index: TreeNodeIndex) {
+        self.get_mut(node_index).metadata.virtual_losses += 1;
+    }
+
+    pub(crate) fn mark_proven(&self, node_index: TreeNodeIndex, proven_result: ProvenResult) {
+        self.get_mut(node_index).proven_result = Some(proven_result);
+    }
+
+    // Proves the node from the results of its children, if possible: it is won if any child is lost
+    // for the opponent, and otherwise, once every child is proven, drawn if any child is drawn, and
+    // lost if not. Returns whether the node is proven.
+    fn prove_from_children(&self, node_index: TreeNodeIndex) -> bool {
+        let node = *self.get(node_index);
+
+        if node.is_proven() {
+            return true;
+        }
+
+        let Some((start, end)) = node.children_info else {
+            return false;
+        };
+
+        let mut proven_result = Some(ProvenResult::Loss);
+
+        for child_index in start..end {
+            match self.get(child_index).proven_result {
+                Some(ProvenResult::Loss) => {
+                    proven_result = Some(ProvenResult::Win);
+                    break;
+                }
+                Some(ProvenResult::Draw) => {
+                    proven_result = proven_result.map(|_| ProvenResult::Draw);
+                }
+                Some(ProvenResult::Win) => {}
+                None => proven_result = None,
+            }
+        }
+
+        if let Some(proven_result) = proven_result {
+            self.mark_proven(node_index, proven_result);
+        }
+
+        proven_result.is_some()
+    }
+
+    /// Proves the ancestors of the last node of a path from the root, from the leaf upwards,
+    /// stopping at the first ancestor which can't be proven yet.
+    pub(crate) fn propagate_proofs(&self, path: &[TreeNodeIndex]) {
+        for &node_index in path.iter().rev().skip(1) {
+            if !self.prove_from_children(node_index) {
+                break;
+            }
+        }
+    }
+
+    /// Selects a path from the root to a leaf, both included, adding a virtual loss to every node on
+    /// it, which is removed when the path is backpropagated.
+    pub(crate) fn select(&self, move_history: usize) -> Selection {
         let mut history = AllocRingBuffer::new(move_history);
-        history.push(self.root_board);
+        history.extend(
+            self.previous_boards[self.previous_boards.len().saturating_sub(move_history)..]
+                .iter()
+                .copied(),
+        );
+        history.push(HistoryBoard::new(self.root_board, &self.root_hashes));
+
+        // Only positions since the last capture or pawn move are needed to detect repetitions
+        let mut hashes = self.root_hashes[self
+            .root_hashes
+            .len()
+            .saturating_sub(self.root_board.min_ply_clock as usize + 1)..]
+            .to_vec();
+
+        self.add_virtual_loss(self.root_index);
 
-        let mut nodes = vec![];
+        let mut nodes = vec![self.root_index];
         let mut last_node = self.get(self.root_index);
+        let mut is_drawn = false;
 
-        loop {
-            if last_node.is_expanded() {
+        // Proven nodes are scored exactly, so there is no need to search below them
+        while last_node.is_expanded() && !last_node.is_proven() {
+            // Nodes drawn on this path are leaves of it, even if other paths expanded them
+            if is_drawn_by_history(&history.back().unwrap().board, &hashes) {
+                is_drawn = true;
                 break;
             }
 
-            nodes.push(self.select_child(&last_node, exploration_rate).unwrap());
-            last_node = self.get(*nodes.last().unwrap());
+            let child_index = self
+                .select_child(&last_node)
+                .expect("expanded node has no children");
+            nodes.push(child_index);
 
-            let mut current_board = *history.back().unwrap();
+            self.add_virtual_loss(child_index);
+
+            last_node = self.get(child_index);
+
+            let mut current_board = history.back().unwrap().board;
             current_board
-                .make_move(
-                    unsafe {
-                        // SAFETY: Children always have initialized metadata
-                        last_node.metadata.assume_init_ref()
-                    }
-                    .chess_move,
-                )
+                .make_move(last_node.metadata.chess_move())
                 .unwrap();
-            history.push(current_board);
+            hashes.push(current_board.hash);
+            history.push(HistoryBoard::new(current_board, &hashes));
+        }
+
+        let leaf_board = &history.back().unwrap().board;
+
+        // Proven leaves are scored exactly however they are reached
+        if !last_node.is_expanded() && !last_node.is_proven() {
+            is_drawn = is_drawn_by_history(leaf_board, &hashes);
         }
 
-        (nodes.into(), history.into_iter().collect())
+        Selection {
+            proven_result: if is_drawn {
+                None
+            } else {
+                last_node
+                    .proven_result
+                    .or_else(|| terminal_result(leaf_board, &hashes))
+            },
+            is_drawn_by_history: is_drawn,
+            transposition_key: transposition_key(leaf_board, &hashes),
+            path: nodes.into(),
+            boards: history.into_iter().collect(),
+        }
     }
 
-    pub(crate) unsafe fn backpropagate(&self, value: f32, nodes: &[TreeNodeIndex]) {
-        for &node in nodes {
-            let mut node = self.get_mut(node);
+    /// Adds the value of a leaf, for its player to move, to every node on the path to it. Nodes hold
+    /// values for the player who made their move, so the value changes sign at every ply.
+    pub(crate) fn backpropagate(&self, leaf_value: f32, nodes: &[TreeNodeIndex]) {
+        let mut value = -leaf_value;
 
-            unsafe {
-                // SAFETY: Children always have initialized metadata
-                let metadata = node.metadata.assume_init_mut();
+        for &node in nodes.iter().rev() {
+            let metadata = &mut self.get_mut(node).metadata;
 
-                metadata.value_sum += value;
-                metadata.visits += 1;
-            }
+            metadata.value_sum += value;
+            metadata.value_square_sum += value * value;
+            metadata.visits += 1;
+            metadata.virtual_losses -= 1;
+
+            value = -value;
         }
     }
 
-    pub fn grow<B: Backend>(&self, network: &Pisa<B>, exploration_rate: f32) {
-        let (path, boards) = self.select(exploration_rate, network.move_history());
-        let end_board = boards.last().unwrap();
-        let network_result = &network.process(vec![&boards[..]])[0];
+    // Expands the node with the children of an expanded node sharing its transposition key, if
+    // there is one, and returns the value of the board.
+    fn transpose(&self, node_index: TreeNodeIndex, transposition_key: u64) -> Option<f32> {
+        let transposition_index = *self
+            .transpositions
+            .as_ref()?
+            .lock()
+            .expect("mutex is poisoned")
+            .get(&transposition_key)?;
+
+        // The children of the root get noise, which other nodes must not share
+        if transposition_index == node_index
+            || (transposition_index == self.root_index && self.root_noise.is_some())
+        {
+            return None;
+        }
 
-        self.expand(
-            *path.last().unwrap(),
-            &mg::gen_moves(end_board)
-                .into_iter()
-                .map(|chess_move| (network_result.move_probabilities[chess_move], chess_move))
-                .collect::<Vec<_>>(),
-        );
+        let transposition = *self.get(transposition_index);
+        let mut node = self.get_mut(node_index);
+
+        if !node.is_expanded() {
+            node.children_info = transposition.children_info;
+            node.value = transposition.value;
+        }
+
+        Some(transposition.value)
+    }
+
+    /// Runs a single playout: selects a leaf, evaluates and expands it, and backpropagates its
+    /// value. Leaves in which the game is over are proven and scored exactly, rather than
+    /// evaluated, and their results are propagated to their ancestors. Leaves drawn by repetition
+    /// or the move-count rules are scored as draws for the playout, but not proven, as these draws
+    /// depend on the path to the leaf. Leaves whose board was
+    /// already expanded elsewhere in the tree share its children and value instead. Any number of
+    /// threads may grow the same tree at once, and their leaves are evaluated together when they
+    /// share an evaluation queue. Returns whether a playout was run, which it isn't once the
+    /// memory limit of the tree is reached.
+    pub fn grow(&self, evaluator: &impl LeafEvaluator) -> bool {
+        if self.is_full() {
+            return false;
+        }
+
+        let Selection {
+            path,
+            boards,
+            proven_result,
+            is_drawn_by_history,
+            transposition_key,
+        } = self.select(evaluator.move_history());
+        let leaf_index = *path.last().unwrap();
+
+        self.depth.fetch_max(path.len() - 1, Ordering::Relaxed);
+
+        let mut is_proven = proven_result.is_some();
+
+        let value = if let Some(proven_result) = proven_result {
+            self.mark_proven(leaf_index, proven_result);
+
+            proven_result.value()
+        } else if is_drawn_by_history {
+            ProvenResult::Draw.value()
+        } else if let Some(value) = self.transpose(leaf_index, transposition_key) {
+            // The children shared with the transposition may already prove the leaf
+            is_proven = self.prove_from_children(leaf_index);
+
+            value
+        } else {
+            let leaf_board = boards.last().unwrap().board;
+            let network_result = evaluator.evaluate(boards);
+            self.evaluations.fetch_add(1, Ordering::Relaxed);
+
+            self.expand(
+                leaf_index,
+                network_result.value,
+                &mg::gen_moves(&leaf_board)
+                    .into_iter()
+                    .map(|chess_move| (network_result.move_probabilities[chess_move], chess_move))
+                    .collect::<Vec<_>>(),
+            );
+
+            if let Some(transpositions) = &self.transpositions {
+                transpositions
+                    .lock()
+                    .expect("mutex is poisoned")
+                    .entry(transposition_key)
+                    .or_insert(leaf_index);
+            }
+
+            network_result.value
+        };
+
+        self.backpropagate(value, &path);
+
+        if is_proven {
+            self.propagate_proofs(&path);
+        }
 
-        // SAFETY: The path was obtained from `Tree::select`
-        unsafe { self.backpropagate(network_result.value, &path) };
+        true
     }
 }
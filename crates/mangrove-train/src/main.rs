@@ -1,6 +1,76 @@
+use std::{path::PathBuf, process::ExitCode};
+
 use burn::backend::Autodiff;
-use burn_wgpu::Wgpu;
+use clap::{Parser, ValueEnum};
+
+#[cfg(not(any(
+    feature = "wgpu",
+    feature = "ndarray",
+    feature = "candle",
+    feature = "tch"
+)))]
+compile_error!(
+    "at least one of the `wgpu`, `ndarray`, `candle` and `tch` features must be enabled"
+);
+
+// The backends the network may be trained on, each behind the feature of the same name. The first
+// enabled backend is the default.
+#[derive(Clone, Copy, ValueEnum)]
+enum BackendKind {
+    #[cfg(feature = "wgpu")]
+    Wgpu,
+    #[cfg(feature = "ndarray")]
+    Ndarray,
+    #[cfg(feature = "candle")]
+    Candle,
+    #[cfg(feature = "tch")]
+    Tch,
+}
+
+#[derive(Parser)]
+#[command(about = "Trains a network for mangrove from self-play games")]
+struct Cli {
+    #[arg(
+        long,
+        help = "The backend the network is trained on. `wgpu` trains it on the GPU, and `ndarray`, `candle` and `tch` on the CPU. Only the backends whose features were enabled at build time are available.",
+        value_enum,
+        default_value_t = BackendKind::value_variants()[0]
+    )]
+    backend: BackendKind,
+    #[arg(
+        long,
+        help = "The file to save the network to after every epoch, without its `.mpk` extension.",
+        default_value = "pisa"
+    )]
+    network: PathBuf,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let network_path = &cli.network;
+
+    let result = match cli.backend {
+        #[cfg(feature = "wgpu")]
+        BackendKind::Wgpu => mangrove_train::train::run::<Autodiff<burn_wgpu::Wgpu>>(network_path),
+        #[cfg(feature = "ndarray")]
+        BackendKind::Ndarray => {
+            mangrove_train::train::run::<Autodiff<burn_ndarray::NdArray>>(network_path)
+        }
+        #[cfg(feature = "candle")]
+        BackendKind::Candle => {
+            mangrove_train::train::run::<Autodiff<burn_candle::Candle>>(network_path)
+        }
+        #[cfg(feature = "tch")]
+        BackendKind::Tch => {
+            mangrove_train::train::run::<Autodiff<burn_tch::LibTorch>>(network_path)
+        }
+    };
+
+    if let Err(error) = result {
+        eprintln!("failed to save network: {error}");
+
+        return ExitCode::FAILURE;
+    }
 
-fn main() {
-    hash_train::train::run::<Autodiff<Wgpu>>();
+    ExitCode::SUCCESS
 }
@@ -1,28 +1,28 @@
-// TODO: Refactor this whole file
-use std::iter;
-
 use burn::tensor::{backend::Backend, Tensor};
-use mangrove_core::{
-    board::Board,
-    game::{Game, Outcome},
+use mangrove_core::game::{Game, Outcome};
+use mangrove_pisa::{boards_to_tensor, HistoryBoard, MoveProbabilities, Pisa, PisaResult};
+use mangrove_search::{
+    exploration::{DirichletNoise, TemperatureSchedule},
+    tree::Tree,
+};
+use rand::Rng;
+
+// The playouts searched from each position before a move is played
+const PLAYOUTS: usize = 20;
+
+// Moves are sampled in proportion to their visits in the opening, so games differ from one
+// another, and the best move is played from then on
+const TEMPERATURE_SCHEDULE: TemperatureSchedule = TemperatureSchedule {
+    temperature: 1.0,
+    cutoff_ply: 30,
+    final_temperature: 0.0,
+};
+
+// The noise mixed into the priors of the root, so moves the network dismisses are still searched
+const ROOT_NOISE: DirichletNoise = DirichletNoise {
+    alpha: 0.3,
+    epsilon: 0.25,
 };
-use mangrove_pisa::model::{MoveProbabilities, Pisa, PisaResult};
-use mangrove_search::tree::Tree;
-use rand::{distributions::WeightedIndex, Rng};
-use ringbuffer::{AllocRingBuffer, RingBuffer};
-
-const EXPANSIONS: usize = 20;
-
-fn expand_tree(
-    tree: &mut Tree,
-    selector: &mut impl Selector,
-    model: &H0<impl Backend>,
-    expansions: usize,
-) {
-    for _ in 0..expansions {
-        tree.expand(selector, model);
-    }
-}
 
 #[derive(Clone)]
 pub struct TrainInput<B: Backend> {
@@ -30,88 +30,82 @@ pub struct TrainInput<B: Backend> {
     pub expected_output: Tensor<B, 1>,
 }
 
-fn make_move(model: &H0<B>, game_boards: &Vec<Board>, rng: &mut impl Rng) {
-    let x = 2;
+// Returns the share of the visits of the root which went to each of its moves, which the network
+// learns to predict
+fn visit_probabilities(tree: &Tree) -> MoveProbabilities {
+    let move_statistics = tree.move_statistics();
+    let visits = move_statistics
+        .iter()
+        .map(|move_statistics| move_statistics.visits)
+        .sum::<u32>()
+        .max(1) as f32;
+
+    MoveProbabilities::new(move_statistics.into_iter().map(|move_statistics| {
+        (
+            move_statistics.visits as f32 / visits,
+            move_statistics.chess_move,
+        )
+    }))
 }
 
-// TODO: Optimize and refactor this code and consider using const-generics for the move history as
-// this could considerably improve performance here. Maybe using a global board array would also
-// improve performance
+/// Plays a game of the network against itself, keeping the searched subtree of every played move,
+/// and returns the positions of the game as training inputs. Games are cut off after `ply_cap` plies,
+/// and scored like draws.
 pub fn gen_game<B: Backend>(
-    model: &H0<B>,
+    network: &Pisa<B>,
     ply_cap: usize,
     rng: &mut impl Rng,
 ) -> Vec<TrainInput<B>> {
-    let mut tree = Tree::new(Board::starting_position());
+    let mut game = Game::starting_position();
+    let mut tree = Tree::new(*game.board()).with_root_noise(ROOT_NOISE);
 
+    // Every board of the game, with its repetitions, from which the histories of its positions are
+    // encoded
+    let mut boards = vec![HistoryBoard::new(*game.board(), game.hashes())];
     let mut positions = Vec::with_capacity(ply_cap);
-    let mut boards = AllocRingBuffer::new(model.move_history());
 
     let outcome = loop {
-        boards.push(*game.board());
-
-        for _ in 0..expansions {
-            tree.expand(selector, model);
+        for _ in 0..PLAYOUTS {
+            tree.grow(network);
         }
 
-        let tree_visits = tree.visits() as f32;
-        let children = tree.children().unwrap();
-
-        let move_probabilities = MoveProbabilities::new(
-            children
-                .iter()
-                .map(|child| (child.tree.visits() as f32 / tree_visits, child.chess_move)),
-        );
-
-        let child_index = rng
-            .sample(WeightedIndex::new(children.iter().map(|child| child.tree.visits())).unwrap());
-
-        let child = children.into_iter().nth(child_index).unwrap();
+        let temperature = TEMPERATURE_SCHEDULE.temperature(game.board().ply());
 
-        game.make_move(child.chess_move);
+        let Some(chess_move) = tree.sample_move(temperature, rng) else {
+            break None;
+        };
 
-        tree = child.tree;
+        positions.push((boards.len(), visit_probabilities(&tree)));
 
-        positions.push((boards.to_vec(), move_probabilities));
+        game.make_move(chess_move).unwrap();
+        tree.try_advance(chess_move).unwrap();
+        boards.push(HistoryBoard::new(*game.board(), game.hashes()));
 
-        if positions.len() >= ply_cap {
-            break Outcome::Draw;
-        } else if let Some(outcome) = game.outcome() {
-            break outcome;
+        if let Some(outcome) = game.outcome() {
+            break Some(outcome);
+        } else if positions.len() >= ply_cap {
+            break None;
         }
     };
 
-    let finishing_color = game.board().playing_color;
-
-    let outcome_value = match outcome {
-        Outcome::Win(_) => 1.0,
-        Outcome::Draw => 0.0,
-    };
-
-    // TODO: Consider splitting on the outcome in this section, or maybe splitting the boards into
-    // ones of the color white and the color black
     positions
         .into_iter()
-        .map(|(boards, move_probabilities)| TrainInput {
-            input: hash_network::boards_to_tensor(
-                boards
-                    .iter()
-                    .map(Some)
-                    .chain(iter::repeat(None).take(model.move_history() - boards.len()))
-                    .collect(),
-            ),
-            expected_output: PisaResult {
-                // If the finshing color, in other words, the color that would play if the game
-                // wasn't finished, actually won, it would mean it would have the ability to
-                // capture the opponent's king, and thus this color always loses.
-                value: if finishing_color != boards.last().unwrap().playing_color {
-                    1.0
-                } else {
-                    -1.0
-                } * outcome_value,
-                move_probabilities,
+        .map(|(board_count, move_probabilities)| {
+            let board = boards[board_count - 1].board;
+
+            TrainInput {
+                input: boards_to_tensor(&boards[..board_count], network.move_history()),
+                expected_output: PisaResult {
+                    // The value of the outcome for the player to move
+                    value: match outcome {
+                        Some(Outcome::Win(color)) if color == board.playing_color => 1.0,
+                        Some(Outcome::Win(_)) => -1.0,
+                        Some(Outcome::Draw(_)) | None => 0.0,
+                    },
+                    move_probabilities,
+                }
+                .into(),
             }
-            .into(),
         })
-        .collect::<Vec<_>>()
+        .collect()
 }
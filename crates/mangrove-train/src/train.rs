@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use burn::{
     grad_clipping::GradientClippingConfig,
     nn::loss::MSELoss,
@@ -9,7 +11,7 @@ use burn::{
         Shape, Tensor,
     },
 };
-use hash_network::model::{BatchOutput, H0Config, H0};
+use mangrove_pisa::{BatchOutput, Pisa, PisaConfig, PisaFileError};
 use rand::Rng;
 use ringbuffer::RingBuffer;
 
@@ -17,7 +19,7 @@ use crate::{play, TrainBuffer};
 
 pub fn add_games<B: Backend>(
     train_buffer: &mut TrainBuffer<B>,
-    model: &H0<B>,
+    model: &Pisa<B>,
     rng: &mut impl Rng,
     ply_cap: usize,
     games: usize,
@@ -69,7 +71,8 @@ fn loss<B: Backend>(
     loss_per_item.mean()
 }
 
-pub fn run<B: AutodiffBackend>() {
+/// Trains a network from self-play games, saving it to the passed path after every epoch.
+pub fn run<B: AutodiffBackend>(network_path: &Path) -> Result<(), PisaFileError> {
     let epochs = 1000;
     let ply_cap = 80;
     let mut games_per_iteration = 8;
@@ -83,7 +86,8 @@ pub fn run<B: AutodiffBackend>() {
         .with_weight_decay(Some(WeightDecayConfig::new(1e-5)))
         .with_gradient_clipping(Some(GradientClippingConfig::Norm(10.0)))
         .init();
-    let mut model = H0Config::new().init::<B>();
+    let config = PisaConfig::new();
+    let mut model = config.init::<B>();
     let mut train_buffer = TrainBuffer::new();
 
     for epoch in 1..epochs + 1 {
@@ -142,8 +146,14 @@ pub fn run<B: AutodiffBackend>() {
             model = optimizer.step(learning_rate, model, gradients);
         }
 
+        model.save(&config, network_path)?;
+
+        println!("SAVED EPOCH {epoch} NETWORK TO {}", network_path.display());
+
         if games_per_iteration < 20000 {
             games_per_iteration <<= 1;
         }
     }
+
+    Ok(())
 }
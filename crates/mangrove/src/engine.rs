@@ -6,24 +6,34 @@ use std::{
     io::{BufRead, Lines, StdinLock},
     iter,
     num::ParseIntError,
+    path::PathBuf,
     str::FromStr,
-    sync::mpsc::{Receiver, Sender},
-    thread,
-    time::Duration,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use burn_wgpu::Wgpu;
+use burn::tensor::backend::Backend;
 use mangrove_core::{
     board::{Board, ParseBoardError},
     repr::{ChessMove, ParseChessMoveError},
 };
-use mangrove_pisa::PisaConfig;
+use mangrove_pisa::{Pisa, PisaConfig};
 use mangrove_search::{
-    search::{self, SearchCommand},
+    cache::{CachedEvaluator, EvaluationCache},
+    search::{self, SearchCommand, SearchHandle, SearchLimits, SearchParameters},
+    selection::SelectionPolicy,
     tree::Tree,
 };
 use tracing::instrument;
 
+use crate::time::TimeManager;
+
+/// How often the time manager checks whether to stop thinking.
+const TIME_MANAGEMENT_INTERVAL: Duration = Duration::from_millis(10);
+
 #[derive(Debug)]
 struct TimeData {
     time_left: Duration,
@@ -160,8 +170,8 @@ impl<'a> MessageReader<'a> {
 }
 
 pub struct Engine<'a> {
-    command_sender: Sender<SearchCommand>,
-    best_move_receiver: Receiver<ChessMove>,
+    search_handle: SearchHandle,
+    evaluation_cache: Option<Arc<EvaluationCache>>,
     times: TimeData,
     increments: IncrementData,
     message_reader: MessageReader<'a>,
@@ -176,6 +186,8 @@ pub enum ProtocolError {
     InvalidSubsequentMessage(#[source] ParseSubsequentMessageError),
     #[error("input stream closed")]
     InputStreamClosed,
+    #[error("no move can be played in the position")]
+    NoMoveToPlay,
 }
 
 enum OutgoingMessage {
@@ -193,18 +205,36 @@ impl Display for OutgoingMessage {
 }
 
 pub struct EngineParameters {
-    pub search_threads: usize,
-    pub exploration_rate: f32,
+    // The file to load the network from, instead of initializing a random one
+    pub network_path: Option<PathBuf>,
+    pub search_parameters: SearchParameters,
+    // The memory the search tree may use, in bytes
+    pub tree_memory_limit: Option<usize>,
+    // The memory the cache of network evaluations may use, in bytes
+    pub evaluation_cache_memory_limit: Option<usize>,
+    pub selection_policy: Box<dyn SelectionPolicy>,
 }
 
 impl<'a> Engine<'a> {
     #[instrument(name = "init engine", skip_all)]
-    pub fn new(
+    pub fn new<B: Backend>(
         engine_parameters: EngineParameters,
         mut message_reader: MessageReader<'a>,
     ) -> Result<Self, Box<dyn Error>> {
-        let network = PisaConfig::new().init::<Wgpu>();
-        tracing::info!("initialized network");
+        let network = match &engine_parameters.network_path {
+            Some(network_path) => {
+                let (network, config) = Pisa::<B>::load(network_path)?;
+                tracing::info!(path = ?network_path, config = %config, "loaded network");
+
+                network
+            }
+            None => {
+                let network = PisaConfig::new().init::<B>();
+                tracing::info!("initialized network");
+
+                network
+            }
+        };
 
         Self::send_message(OutgoingMessage::Ready);
 
@@ -221,17 +251,31 @@ impl<'a> Engine<'a> {
             "received initial message",
         );
 
-        let (command_sender, best_move_receiver) = search::start_search_thread(
-            Tree::new(board),
-            network,
-            engine_parameters.exploration_rate,
-        );
+        let mut tree = Tree::new(board).with_selection_policy(engine_parameters.selection_policy);
+
+        if let Some(tree_memory_limit) = engine_parameters.tree_memory_limit {
+            tree = tree.with_memory_limit(tree_memory_limit);
+        }
+
+        let evaluation_cache = engine_parameters
+            .evaluation_cache_memory_limit
+            .map(|memory_limit| Arc::new(EvaluationCache::new(memory_limit)));
+
+        let search_handle = if let Some(evaluation_cache) = &evaluation_cache {
+            search::start_search_thread(
+                tree,
+                CachedEvaluator::new(network, evaluation_cache.clone()),
+                engine_parameters.search_parameters,
+            )
+        } else {
+            search::start_search_thread(tree, network, engine_parameters.search_parameters)
+        };
 
         tracing::info!("started search thread");
 
         Ok(Self {
-            command_sender,
-            best_move_receiver,
+            search_handle,
+            evaluation_cache,
             times,
             increments,
             message_reader,
@@ -242,16 +286,57 @@ impl<'a> Engine<'a> {
         print!("{outgoing_message}");
     }
 
-    fn calculate_thinking_time(&self) -> Duration {
-        Duration::from_secs(10)
-    }
-
     fn think(&mut self) -> Result<(), Box<dyn Error>> {
-        thread::sleep(self.calculate_thinking_time());
+        let start = Instant::now();
+
+        let mut time_manager = TimeManager::new(
+            self.times.time_left,
+            self.increments.increment,
+            self.times.opponent_time_left,
+            &self.search_handle.root_board(),
+            &self.search_handle.root_statistics(),
+        );
 
-        self.command_sender
-            .send(SearchCommand::SendAndPlayBestMove)?;
-        let best_move = self.best_move_receiver.recv()?;
+        // The search stops itself at the maximum time, or once the best move is unassailable
+        self.search_handle
+            .command_sender
+            .send(SearchCommand::Search(SearchLimits {
+                max_time: Some(time_manager.maximum_time()),
+                stop_when_unassailable: true,
+                ..Default::default()
+            }))?;
+
+        let search_result = loop {
+            match self
+                .search_handle
+                .result_receiver
+                .recv_timeout(TIME_MANAGEMENT_INTERVAL)
+            {
+                Ok(search_result) => break search_result,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(error @ RecvTimeoutError::Disconnected) => return Err(error.into()),
+            }
+
+            if time_manager.should_stop(start.elapsed(), &self.search_handle.root_statistics()) {
+                self.search_handle
+                    .command_sender
+                    .send(SearchCommand::Stop)?;
+
+                break self.search_handle.result_receiver.recv()?;
+            }
+        };
+
+        tracing::info!(
+            elapsed = ?start.elapsed(),
+            stop_reason = ?search_result.stop_reason,
+            cache_hit_rate = ?self
+                .evaluation_cache
+                .as_ref()
+                .and_then(|evaluation_cache| evaluation_cache.statistics().hit_rate()),
+            "stopped thinking",
+        );
+
+        let best_move = search_result.best_move.ok_or(ProtocolError::NoMoveToPlay)?;
 
         Self::send_message(OutgoingMessage::BestMove(best_move));
 
@@ -269,8 +354,17 @@ impl<'a> Engine<'a> {
         );
 
         self.times = times;
-        self.command_sender
-            .send(SearchCommand::PlayedMove(played_move))?;
+
+        let (advance_result_sender, advance_result_receiver) = mpsc::channel();
+        self.search_handle
+            .command_sender
+            .send(SearchCommand::PlayedMove(
+                played_move,
+                advance_result_sender,
+            ))?;
+
+        // Moves which can't be played from the root are illegal or invalid
+        advance_result_receiver.recv()??;
 
         Ok(())
     }
@@ -1,14 +1,35 @@
 mod engine;
+mod perft;
+mod time;
 
-use std::{error::Error, fs::File, io, path::PathBuf};
+use std::{error::Error, fs::File, io, path::PathBuf, str::FromStr, time::Duration};
 
+use burn::tensor::backend::Backend;
 use clap::{
     builder::{styling::AnsiColor, Styles},
-    Parser, Subcommand,
+    Parser, Subcommand, ValueEnum,
 };
 use engine::{Engine, EngineParameters, MessageReader};
+use mangrove_core::{board::Board, perft::PerftTable};
+use mangrove_search::{
+    evaluation::BatchParameters,
+    exploration::TemperatureSchedule,
+    search::SearchParameters,
+    selection::{FirstPlayUrgency, LogPuct, Puct, SelectionPolicy, VariancePuct},
+};
+use perft::PerftParameters;
 use tracing::Level;
 
+#[cfg(not(any(
+    feature = "wgpu",
+    feature = "ndarray",
+    feature = "candle",
+    feature = "tch"
+)))]
+compile_error!(
+    "at least one of the `wgpu`, `ndarray`, `candle` and `tch` features must be enabled"
+);
+
 fn styles() -> Styles {
     Styles::styled()
         .header(AnsiColor::Yellow.on_default())
@@ -39,6 +60,52 @@ struct Cli {
     command: Command,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum SelectionPolicyKind {
+    Puct,
+    LogPuct,
+    VariancePuct,
+}
+
+impl SelectionPolicyKind {
+    fn selection_policy(
+        self,
+        exploration_rate: f32,
+        first_play_urgency: FirstPlayUrgency,
+    ) -> Box<dyn SelectionPolicy> {
+        match self {
+            Self::Puct => Box::new(Puct {
+                exploration_rate,
+                first_play_urgency,
+            }),
+            Self::LogPuct => Box::new(LogPuct {
+                exploration_rate,
+                first_play_urgency,
+                ..Default::default()
+            }),
+            Self::VariancePuct => Box::new(VariancePuct {
+                exploration_rate,
+                first_play_urgency,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+// The backends the network may run on, each behind the feature of the same name. The first enabled
+// backend is the default.
+#[derive(Clone, Copy, ValueEnum)]
+enum BackendKind {
+    #[cfg(feature = "wgpu")]
+    Wgpu,
+    #[cfg(feature = "ndarray")]
+    Ndarray,
+    #[cfg(feature = "candle")]
+    Candle,
+    #[cfg(feature = "tch")]
+    Tch,
+}
+
 #[derive(Subcommand)]
 enum Command {
     #[command(about = "Begin a CEGO session")]
@@ -50,13 +117,98 @@ enum Command {
             default_value_t = 1
         )]
         search_threads: usize,
+        #[arg(
+            long,
+            help = "The backend the network runs on. `wgpu` runs it on the GPU, and `ndarray`, `candle` and `tch` on the CPU. Only the backends whose features were enabled at build time are available.",
+            value_enum,
+            default_value_t = BackendKind::value_variants()[0]
+        )]
+        backend: BackendKind,
+        #[arg(
+            long,
+            help = "The file to load the network from, as saved by the trainer, without its `.mpk` extension. A randomly initialized network is used by default."
+        )]
+        network: Option<PathBuf>,
+        #[arg(
+            short = 'b',
+            long,
+            help = "The maximum number of positions the network evaluates at once.",
+            default_value_t = 1
+        )]
+        batch_size: usize,
+        #[arg(
+            long,
+            help = "The time to wait for a batch of positions to fill before evaluating it anyway, in microseconds.",
+            default_value_t = 1000
+        )]
+        batch_timeout: u64,
+        #[arg(
+            long,
+            help = "The memory the search tree may use, in MiB. The tree stops growing once it reaches the limit, until a move is played. There is no limit by default."
+        )]
+        tree_memory: Option<usize>,
+        #[arg(
+            long,
+            help = "The memory the cache of network evaluations may use, in MiB. Positions reached again, across moves or through transpositions, are looked up in it instead of being evaluated. No cache is used when this is 0.",
+            default_value_t = 256
+        )]
+        cache_memory: usize,
+        #[arg(
+            long,
+            help = "The policy choosing which moves to search. `log-puct` grows the exploration rate with the visits of positions, and `variance-puct` explores moves with more uncertain values more.",
+            value_enum,
+            default_value_t = SelectionPolicyKind::Puct
+        )]
+        selection_policy: SelectionPolicyKind,
         #[arg(
             short = 'e',
             long,
-            help = "The exploration rate to use for PUCT.",
-            default_value_t = 4.0
+            help = "The exploration rate of the selection policy.",
+            default_value_t = 1.75
         )]
         exploration_rate: f32,
+        #[arg(
+            long,
+            help = "How much lower than the value of a position its unvisited moves are scored.",
+            default_value_t = 0.3
+        )]
+        fpu_reduction: f32,
+    },
+    #[command(
+        about = "Count the leaf nodes of the move tree of a position, to validate move generation"
+    )]
+    Perft {
+        #[arg(
+            help = "The position to count from, as FEN. Defaults to the starting position.",
+            conflicts_with = "suite_file"
+        )]
+        fen: Option<String>,
+        #[arg(
+            short = 'd',
+            long,
+            help = "The depth to count to. When checking a suite file, this is the maximum depth checked.",
+            default_value_t = 5
+        )]
+        depth: u32,
+        #[arg(
+            short = 's',
+            long,
+            help = "An EPD file of perft suites to check, with a position on each line followed by its known results, as in `<fen> ;D1 20 ;D2 400`."
+        )]
+        suite_file: Option<PathBuf>,
+        #[arg(
+            short = 't',
+            long,
+            help = "The number of threads to count with.",
+            default_value_t = 1
+        )]
+        threads: usize,
+        #[arg(
+            long,
+            help = "The size of the table of perft results shared by the threads, in MiB. No table is used when this is 0.",
+            default_value_t = 0
+        )]
+        hash_size: usize,
     },
 }
 
@@ -71,15 +223,21 @@ fn initialize_tracing(trace_file: PathBuf, tracing_level: Level) -> Result<(), B
     Ok(tracing::subscriber::set_global_default(subscriber)?)
 }
 
-fn run(search_threads: usize, exploration_rate: f32) -> Result<(), Box<dyn Error>> {
-    Engine::new(
-        EngineParameters {
-            search_threads,
-            exploration_rate,
-        },
-        MessageReader::new(io::stdin().lock()),
-    )?
-    .run()
+fn run_on<B: Backend>(engine_parameters: EngineParameters) -> Result<(), Box<dyn Error>> {
+    Engine::new::<B>(engine_parameters, MessageReader::new(io::stdin().lock()))?.run()
+}
+
+fn run(backend: BackendKind, engine_parameters: EngineParameters) -> Result<(), Box<dyn Error>> {
+    match backend {
+        #[cfg(feature = "wgpu")]
+        BackendKind::Wgpu => run_on::<burn_wgpu::Wgpu>(engine_parameters),
+        #[cfg(feature = "ndarray")]
+        BackendKind::Ndarray => run_on::<burn_ndarray::NdArray>(engine_parameters),
+        #[cfg(feature = "candle")]
+        BackendKind::Candle => run_on::<burn_candle::Candle>(engine_parameters),
+        #[cfg(feature = "tch")]
+        BackendKind::Tch => run_on::<burn_tch::LibTorch>(engine_parameters),
+    }
 }
 
 pub fn cli() -> Result<(), Box<dyn Error>> {
@@ -92,7 +250,58 @@ pub fn cli() -> Result<(), Box<dyn Error>> {
     match cli.command {
         Command::Run {
             search_threads,
+            backend,
+            network,
+            batch_size,
+            batch_timeout,
+            tree_memory,
+            cache_memory,
+            selection_policy,
             exploration_rate,
-        } => run(search_threads, exploration_rate),
+            fpu_reduction,
+        } => run(
+            backend,
+            EngineParameters {
+                network_path: network,
+                search_parameters: SearchParameters {
+                    search_threads,
+                    batch_parameters: BatchParameters {
+                        batch_size,
+                        timeout: Duration::from_micros(batch_timeout),
+                    },
+                    ponder: true,
+                    temperature_schedule: TemperatureSchedule::default(),
+                },
+                tree_memory_limit: tree_memory.map(|tree_memory| tree_memory << 20),
+                evaluation_cache_memory_limit: (cache_memory != 0).then_some(cache_memory << 20),
+                selection_policy: selection_policy
+                    .selection_policy(exploration_rate, FirstPlayUrgency::Reduction(fpu_reduction)),
+            },
+        ),
+        Command::Perft {
+            fen,
+            depth,
+            suite_file,
+            threads,
+            hash_size,
+        } => {
+            let perft_parameters = PerftParameters {
+                threads,
+                table: (hash_size != 0).then(|| PerftTable::new(hash_size << 20)),
+            };
+
+            if let Some(suite_file) = suite_file {
+                return perft::run_suite_file(&suite_file, depth, &perft_parameters);
+            }
+
+            let board = match fen {
+                Some(fen) => Board::from_str(&fen)?,
+                None => Board::starting_position(),
+            };
+
+            perft::divide(&board, depth, &perft_parameters);
+
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,186 @@
+use std::{
+    error::Error,
+    fs,
+    num::ParseIntError,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use mangrove_core::{
+    board::{Board, ParseBoardError},
+    perft::PerftTable,
+};
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ParsePerftSuiteError {
+    #[error("position must be valid fen")]
+    InvalidBoard(#[source] ParseBoardError),
+    #[error("perft entry must be of the form `D<depth> <nodes>`")]
+    MalformedEntry,
+    #[error("perft entry depth and nodes must be unsigned integers")]
+    InvalidNumber(#[source] ParseIntError),
+}
+
+/// A position along with its known perft results, as stored in a line of an EPD perft suite, such
+/// as `<fen> ;D1 20 ;D2 400`.
+struct PerftSuite {
+    board: Board,
+    entries: Vec<(u32, u64)>,
+}
+
+impl FromStr for PerftSuite {
+    type Err = ParsePerftSuiteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(';');
+
+        // SAFETY: Splitting always yields at least one part
+        let position = parts.next().unwrap().trim();
+
+        // EPD positions may omit the move counters FEN requires
+        let board = match position.split(' ').count() {
+            4 => Board::from_str(&format!("{position} 0 1")),
+            _ => Board::from_str(position),
+        }
+        .map_err(ParsePerftSuiteError::InvalidBoard)?;
+
+        let entries = parts
+            .map(|entry| {
+                let (depth, nodes) = entry
+                    .trim()
+                    .strip_prefix('D')
+                    .and_then(|entry| entry.split_once(' '))
+                    .ok_or(ParsePerftSuiteError::MalformedEntry)?;
+
+                Ok((
+                    depth.parse().map_err(ParsePerftSuiteError::InvalidNumber)?,
+                    nodes
+                        .trim()
+                        .parse()
+                        .map_err(ParsePerftSuiteError::InvalidNumber)?,
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { board, entries })
+    }
+}
+
+fn nodes_per_second(nodes: u64, elapsed: Duration) -> u64 {
+    (nodes as f64 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64
+}
+
+/// How perft nodes are counted: the number of threads to count with, and the table shared by
+/// them, if any.
+pub struct PerftParameters {
+    pub threads: usize,
+    pub table: Option<PerftTable>,
+}
+
+impl PerftParameters {
+    fn perft(&self, board: &Board, depth: u32) -> u64 {
+        board.parallel_perft(depth, self.threads, self.table.as_ref())
+    }
+}
+
+/// Prints the number of leaf nodes under each legal move of the board at the passed depth, along
+/// with their total and the speed at which they were counted.
+pub fn divide(board: &Board, depth: u32, perft_parameters: &PerftParameters) {
+    let start = Instant::now();
+    let mut nodes = 0;
+
+    if depth == 0 {
+        nodes = 1;
+    } else {
+        for (chess_move, child_board) in board.gen_child_boards() {
+            let move_nodes = perft_parameters.perft(&child_board, depth - 1);
+
+            println!("{}: {move_nodes}", board.castle_to_king_target(chess_move));
+
+            nodes += move_nodes;
+        }
+    }
+
+    let elapsed = start.elapsed();
+
+    println!();
+    println!("Nodes searched: {nodes}");
+    println!("Time: {:.3}s", elapsed.as_secs_f64());
+    println!("Nodes per second: {}", nodes_per_second(nodes, elapsed));
+}
+
+/// Checks every position in the EPD perft suite file against its known perft results, up to the
+/// passed maximum depth, and prints whether each line passed.
+pub fn run_suite_file(
+    path: &Path,
+    max_depth: u32,
+    perft_parameters: &PerftParameters,
+) -> Result<(), Box<dyn Error>> {
+    let start = Instant::now();
+    let mut nodes = 0;
+    let mut failed_lines = 0;
+    let mut checked_lines = 0;
+
+    for (line_index, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line_number = line_index + 1;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        checked_lines += 1;
+
+        let suite = match PerftSuite::from_str(line) {
+            Ok(suite) => suite,
+            Err(error) => {
+                failed_lines += 1;
+                println!("line {line_number}: invalid ({error})");
+
+                continue;
+            }
+        };
+
+        let mut failures = Vec::new();
+
+        for &(depth, expected_nodes) in &suite.entries {
+            if depth > max_depth {
+                continue;
+            }
+
+            let found_nodes = perft_parameters.perft(&suite.board, depth);
+            nodes += found_nodes;
+
+            if found_nodes != expected_nodes {
+                failures.push(format!(
+                    "depth {depth} expected {expected_nodes}, found {found_nodes}"
+                ));
+            }
+        }
+
+        if failures.is_empty() {
+            println!("line {line_number}: pass");
+        } else {
+            failed_lines += 1;
+            println!("line {line_number}: fail ({})", failures.join(", "));
+        }
+    }
+
+    let elapsed = start.elapsed();
+
+    println!();
+    println!(
+        "Passed {} of {checked_lines} lines",
+        checked_lines - failed_lines
+    );
+    println!("Nodes searched: {nodes}");
+    println!("Time: {:.3}s", elapsed.as_secs_f64());
+    println!("Nodes per second: {}", nodes_per_second(nodes, elapsed));
+
+    if failed_lines == 0 {
+        Ok(())
+    } else {
+        Err(format!("{failed_lines} perft suite lines failed").into())
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use mangrove_core::{board::Board, repr::ChessMove};
+use mangrove_search::tree::RootStatistics;
+
+/// Time kept aside for each move, to cover the latency between the engine and the clock.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+/// The number of moves the remaining time is split between in the endgame, and the number added
+/// to it in the opening, as games usually last longer from there.
+const ENDGAME_MOVES_TO_GO: f64 = 20.0;
+const OPENING_EXTRA_MOVES_TO_GO: f64 = 20.0;
+
+/// The greatest multiple of the optimum time a single move may take.
+const MAXIMUM_TIME_FACTOR: f64 = 4.0;
+
+/// The greatest share of the remaining time a single move may take.
+const MAXIMUM_TIME_SHARE: f64 = 0.3;
+
+/// The share of the visits of the root above which the best move is considered settled.
+const SETTLED_VISIT_SHARE: f64 = 0.9;
+
+/// The phase weights of knights, bishops, rooks and queens, and the total weight of the starting
+/// position.
+const PHASE_WEIGHTS: [u32; 4] = [1, 1, 2, 4];
+const OPENING_PHASE: u32 = 24;
+
+/// Returns how far the game is from the endgame, from 0 when only kings and pawns are left, to 1
+/// when all pieces are on the board.
+fn game_phase(board: &Board) -> f64 {
+    let phase = [&board.us, &board.them]
+        .into_iter()
+        .flat_map(|player| {
+            [player.knights, player.bishops, player.rooks, player.queens]
+                .into_iter()
+                .zip(PHASE_WEIGHTS)
+                .map(|(pieces, weight)| pieces.count_ones() * weight)
+        })
+        .sum::<u32>();
+
+    (phase.min(OPENING_PHASE) as f64) / (OPENING_PHASE as f64)
+}
+
+/// Decides when to stop searching a move. Each move gets an optimum time, from the remaining time,
+/// the increment, the opponent's clock and the phase of the game, which is stretched while the best
+/// move keeps changing and shortened once it is settled. The search itself enforces the maximum
+/// time, and stops as soon as the second best move can no longer catch up with the best one.
+pub struct TimeManager {
+    optimum_time: Duration,
+    maximum_time: Duration,
+    best_move: Option<ChessMove>,
+    // When the best move last changed, since the search of the move started
+    best_move_changed_at: Duration,
+}
+
+impl TimeManager {
+    pub fn new(
+        time_left: Duration,
+        increment: Duration,
+        opponent_time_left: Duration,
+        board: &Board,
+        root_statistics: &RootStatistics,
+    ) -> Self {
+        let usable_time = time_left.saturating_sub(MOVE_OVERHEAD).as_secs_f64();
+        let moves_to_go = ENDGAME_MOVES_TO_GO + OPENING_EXTRA_MOVES_TO_GO * game_phase(board);
+
+        // Spend more time when ahead on the clock, and less when behind
+        let clock_ratio = (time_left.as_secs_f64()
+            / opponent_time_left.as_secs_f64().max(f64::EPSILON))
+        .clamp(0.5, 2.0);
+
+        let optimum_time =
+            (usable_time / moves_to_go + increment.as_secs_f64() * 0.75) * clock_ratio.sqrt();
+        let maximum_time =
+            (optimum_time * MAXIMUM_TIME_FACTOR).min(usable_time * MAXIMUM_TIME_SHARE);
+
+        Self {
+            optimum_time: Duration::from_secs_f64(optimum_time.min(maximum_time)),
+            maximum_time: Duration::from_secs_f64(maximum_time),
+            best_move: root_statistics.best_move,
+            best_move_changed_at: Duration::ZERO,
+        }
+    }
+
+    /// The time after which the search must stop, whatever the state of the root.
+    pub fn maximum_time(&self) -> Duration {
+        self.maximum_time
+    }
+
+    /// Returns whether the search should stop before the maximum time, given the time spent on it
+    /// so far and the current statistics of the root.
+    pub fn should_stop(&mut self, elapsed: Duration, root_statistics: &RootStatistics) -> bool {
+        if root_statistics.best_move != self.best_move {
+            self.best_move = root_statistics.best_move;
+            self.best_move_changed_at = elapsed;
+        }
+
+        if root_statistics.visits == 0 {
+            return false;
+        }
+
+        // Keep searching while the best move is recent, and stop sooner once it has held for most
+        // of the search
+        let stability = (elapsed - self.best_move_changed_at).as_secs_f64()
+            / elapsed.as_secs_f64().max(f64::EPSILON);
+        let visit_share = root_statistics.best_move_visits as f64 / root_statistics.visits as f64;
+
+        let mut time_factor = 1.5 - stability;
+
+        if visit_share > SETTLED_VISIT_SHARE {
+            time_factor *= 0.5;
+        }
+
+        elapsed.as_secs_f64() >= self.optimum_time.as_secs_f64() * time_factor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, time::Duration};
+
+    use mangrove_core::{board::Board, repr::ChessMove};
+    use mangrove_search::tree::RootStatistics;
+    use test_case::test_case;
+
+    use super::TimeManager;
+
+    // Only kings and pawns are left, so the remaining time is split between the fewest moves
+    const ENDGAME_FEN: &str = "4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1";
+
+    fn root_statistics(best_move: &str, visits: u32, best_move_visits: u32) -> RootStatistics {
+        RootStatistics {
+            visits,
+            best_move: Some(ChessMove::from_str(best_move).unwrap()),
+            best_move_visits,
+            second_best_move_visits: visits - best_move_visits,
+        }
+    }
+
+    fn time_manager(
+        time_left: f64,
+        increment: f64,
+        opponent_time_left: f64,
+        fen: &str,
+    ) -> TimeManager {
+        TimeManager::new(
+            Duration::from_secs_f64(time_left),
+            Duration::from_secs_f64(increment),
+            Duration::from_secs_f64(opponent_time_left),
+            &Board::from_str(fen).unwrap(),
+            &root_statistics("e2e4", 0, 0),
+        )
+    }
+
+    fn assert_close(duration: Duration, seconds: f64) {
+        assert!(
+            (duration.as_secs_f64() - seconds).abs() < 1e-6,
+            "{duration:?} isn't {seconds}s"
+        );
+    }
+
+    #[test_case(0.0, 59.95 / 20.0; "without increment")]
+    #[test_case(1.0, 59.95 / 20.0 + 0.75; "with increment")]
+    fn budget_tests(increment: f64, expected_optimum_time: f64) {
+        let time_manager = time_manager(60.0, increment, 60.0, ENDGAME_FEN);
+
+        assert_close(time_manager.optimum_time, expected_optimum_time);
+        assert_close(time_manager.maximum_time, expected_optimum_time * 4.0);
+    }
+
+    #[test]
+    fn opening_budget_test() {
+        let time_manager = time_manager(
+            60.0,
+            0.0,
+            60.0,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        );
+
+        // Games usually last longer from the opening, so the time is split between more moves
+        assert_close(time_manager.optimum_time, 59.95 / 40.0);
+    }
+
+    #[test_case(1.0, 0.95 * 0.3; "low clock")]
+    #[test_case(0.02, 0.0; "clock below move overhead")]
+    fn low_clock_tests(time_left: f64, expected_maximum_time: f64) {
+        let time_manager = time_manager(time_left, 1.0, time_left, ENDGAME_FEN);
+
+        // A single move never takes more than a share of the remaining time, however large the
+        // increment
+        assert_close(time_manager.maximum_time, expected_maximum_time);
+        assert!(time_manager.optimum_time <= time_manager.maximum_time);
+    }
+
+    #[test_case(30.0, 2.0; "ahead on the clock")]
+    #[test_case(120.0, 0.5; "behind on the clock")]
+    #[test_case(15.0, 2.0; "far ahead on the clock")]
+    #[test_case(0.0, 2.0; "opponent out of time")]
+    fn opponent_clock_tests(opponent_time_left: f64, expected_clock_ratio: f64) {
+        let time_manager = time_manager(60.0, 0.0, opponent_time_left, ENDGAME_FEN);
+
+        assert_close(
+            time_manager.optimum_time,
+            59.95 / 20.0 * f64::sqrt(expected_clock_ratio),
+        );
+    }
+
+    #[test]
+    fn stable_best_move_test() {
+        let mut time_manager = time_manager(60.0, 0.0, 60.0, ENDGAME_FEN);
+        let root_statistics = root_statistics("e2e4", 100, 60);
+
+        // A best move held for the whole search stops it at half the optimum time
+        assert!(!time_manager.should_stop(Duration::from_secs_f64(1.4), &root_statistics));
+        assert!(time_manager.should_stop(Duration::from_secs_f64(1.6), &root_statistics));
+    }
+
+    #[test]
+    fn settled_visit_share_test() {
+        let mut time_manager = time_manager(60.0, 0.0, 60.0, ENDGAME_FEN);
+        let root_statistics = root_statistics("e2e4", 100, 95);
+
+        // Once the best move has most visits, the search stops at a quarter of the optimum time
+        assert!(!time_manager.should_stop(Duration::from_secs_f64(0.7), &root_statistics));
+        assert!(time_manager.should_stop(Duration::from_secs_f64(0.8), &root_statistics));
+    }
+
+    #[test]
+    fn changed_best_move_test() {
+        let mut time_manager = time_manager(60.0, 0.0, 60.0, ENDGAME_FEN);
+        let root_statistics = root_statistics("d2d4", 100, 60);
+
+        // The best move changes after 1.5s, so the search goes on past the time a stable best move
+        // would have stopped it at, until the new best move has held for long enough
+        assert!(!time_manager.should_stop(Duration::from_secs_f64(1.5), &root_statistics));
+        assert!(!time_manager.should_stop(Duration::from_secs_f64(2.0), &root_statistics));
+        assert!(time_manager.should_stop(Duration::from_secs_f64(4.0), &root_statistics));
+    }
+
+    #[test]
+    fn unvisited_root_test() {
+        let mut time_manager = time_manager(60.0, 0.0, 60.0, ENDGAME_FEN);
+
+        assert!(!time_manager.should_stop(
+            Duration::from_secs_f64(10.0),
+            &RootStatistics {
+                visits: 0,
+                best_move: None,
+                best_move_visits: 0,
+                second_best_move_visits: 0,
+            }
+        ));
+    }
+}